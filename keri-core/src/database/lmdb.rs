@@ -1,6 +1,7 @@
 use super::EventDatabase;
 use crate::{
     derivation::attached_signature_code::get_sig_count,
+    event::sections::seal::EventSeal,
     prefix::{
         AttachedSignaturePrefix, BasicPrefix, IdentifierPrefix, Prefix, SelfAddressingPrefix,
         SelfSigningPrefix,
@@ -410,13 +411,13 @@ impl EventDatabase for LmdbEventDatabase {
         &self,
         pref: &IdentifierPrefix,
         dig: &SelfAddressingPrefix,
-        signer: &IdentifierPrefix,
+        validator_seal: &EventSeal,
         sig: &AttachedSignaturePrefix,
     ) -> Result<(), Self::Error> {
         self.write_ref_multi(
             &self.receipts_t,
             &Vec::from(ContentIndex(pref, dig)),
-            &(signer, sig),
+            &(validator_seal, sig),
         )
     }
 
@@ -438,13 +439,13 @@ impl EventDatabase for LmdbEventDatabase {
         &self,
         pref: &IdentifierPrefix,
         dig: &SelfAddressingPrefix,
-        signer: &IdentifierPrefix,
+        validator_seal: &EventSeal,
         sig: &AttachedSignaturePrefix,
     ) -> Result<(), Self::Error> {
         self.write_ref_multi(
             &self.escrowed_receipts_nt,
             &Vec::from(ContentIndex(pref, dig)),
-            &(signer, sig),
+            &(validator_seal, sig),
         )
     }
 }
@@ -0,0 +1,486 @@
+use std::{
+    collections::HashMap,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use crate::{
+    database::{is_head_state, DbIter, EventAcceptance, EventStorage},
+    error::Error,
+    event::EventMessage,
+    event_message::{
+        key_event_message::KeyEvent,
+        signed_event_message::{
+            SignedEventMessage, SignedNontransferableReceipt, SignedTransferableReceipt,
+            TimestampedSignedEventMessage,
+        },
+        TimestampedEventMessage,
+    },
+    prefix::{IdentifierPrefix, Prefix},
+    state::IdentifierState,
+};
+
+#[cfg(feature = "query")]
+use crate::query::reply::SignedReply;
+
+/// In-memory table of `Vec<T>` per identifier
+///
+struct MemoryTable<T> {
+    values: RwLock<HashMap<String, Vec<T>>>,
+}
+
+impl<T> Default for MemoryTable<T> {
+    fn default() -> Self {
+        Self {
+            values: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> MemoryTable<T> {
+    fn read(&self) -> Result<RwLockReadGuard<'_, HashMap<String, Vec<T>>>, Error> {
+        self.values.read().map_err(|_| Error::MutexPoisoned)
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_, HashMap<String, Vec<T>>>, Error> {
+        self.values.write().map_err(|_| Error::MutexPoisoned)
+    }
+
+    /// clones all values of the identifier
+    ///
+    fn iter_values(&self, id: &IdentifierPrefix) -> Result<Option<DbIter<T>>, Error> {
+        Ok(self
+            .read()?
+            .get(&id.to_str())
+            .map(|values| values.clone().into_iter()))
+    }
+
+    /// all values of all the identifiers
+    ///
+    #[cfg(feature = "query")]
+    fn get_all(&self) -> Result<Option<DbIter<T>>, Error> {
+        let values = self.read()?;
+        Ok(Some(
+            values
+                .values()
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>()
+                .into_iter(),
+        ))
+    }
+
+    /// replaces all values of the identifier
+    ///
+    fn put(&self, id: &IdentifierPrefix, values: Vec<T>) -> Result<(), Error> {
+        self.write()?.insert(id.to_str(), values);
+        Ok(())
+    }
+
+    fn push(&self, id: &IdentifierPrefix, value: T) -> Result<(), Error> {
+        self.write()?.entry(id.to_str()).or_default().push(value);
+        Ok(())
+    }
+
+    /// pushes `value` unless it's already present
+    ///
+    fn push_unique(&self, id: &IdentifierPrefix, value: T) -> Result<(), Error> {
+        let mut values = self.write()?;
+        let values = values.entry(id.to_str()).or_default();
+        if !values.contains(&value) {
+            values.push(value);
+        }
+        Ok(())
+    }
+
+    /// pushes `value` in place of the values matching `replaced`
+    ///
+    fn replace(
+        &self,
+        id: &IdentifierPrefix,
+        value: T,
+        replaced: impl Fn(&T) -> bool,
+    ) -> Result<(), Error> {
+        let mut values = self.write()?;
+        let values = values.entry(id.to_str()).or_default();
+        values.retain(|v| !replaced(v));
+        values.push(value);
+        Ok(())
+    }
+
+    /// removes all values equal to `value`
+    ///
+    fn remove(&self, id: &IdentifierPrefix, value: &T) -> Result<(), Error> {
+        if let Some(values) = self.write()?.get_mut(&id.to_str()) {
+            values.retain(|v| v != value);
+        }
+        Ok(())
+    }
+}
+
+/// In-memory event database
+///
+/// Keeps everything `SledEventDatabase` does without touching the disk,
+/// for tests and short-lived verifiers. All the data is dropped along
+/// with it.
+#[derive(Default)]
+pub struct MemoryEventDatabase {
+    key_event_logs: MemoryTable<TimestampedSignedEventMessage>,
+    key_states: RwLock<HashMap<String, IdentifierState>>,
+    likely_duplicious_events: MemoryTable<TimestampedEventMessage>,
+    duplicitous_events: MemoryTable<TimestampedSignedEventMessage>,
+    out_of_order_events: MemoryTable<TimestampedSignedEventMessage>,
+    partially_signed_events: MemoryTable<TimestampedSignedEventMessage>,
+    partially_witnessed_events: MemoryTable<TimestampedSignedEventMessage>,
+    receipts_nt: MemoryTable<SignedNontransferableReceipt>,
+    escrowed_receipts_nt: MemoryTable<SignedNontransferableReceipt>,
+    receipts_t: MemoryTable<SignedTransferableReceipt>,
+    escrowed_receipts_t: MemoryTable<SignedTransferableReceipt>,
+
+    #[cfg(feature = "query")]
+    accepted_rpy: MemoryTable<SignedReply>,
+
+    #[cfg(feature = "query")]
+    escrowed_replys: MemoryTable<SignedReply>,
+}
+
+impl MemoryEventDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl EventStorage for MemoryEventDatabase {
+    fn add_kel_finalized_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        self.key_event_logs.push(id, event.into())?;
+        self.remove_key_state(id)
+    }
+
+    fn get_kel_finalized_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.key_event_logs.iter_values(id)
+    }
+
+    fn remove_kel_finalized_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        self.key_event_logs.remove(id, &event.into())?;
+        self.remove_key_state(id)
+    }
+
+    /// Accept Event
+    ///
+    /// The KEL stays locked from checking the acceptance against it until
+    /// the event and its key state are in, the other records follow.
+    fn accept_event(&self, acceptance: EventAcceptance) -> Result<(), Error> {
+        let id = &acceptance.event.event_message.event.get_prefix();
+        {
+            let mut kels = self.key_event_logs.write()?;
+            let kel = kels.get(&id.to_str()).into_iter().flatten();
+            if !acceptance.extends(kel.map(|event| &event.signed_event_message)) {
+                return Err(Error::KelChangedError);
+            }
+            let kel = kels.entry(id.to_str()).or_default();
+            kel.retain(|event| !acceptance.superseded.contains(&event.signed_event_message));
+            kel.push(acceptance.event.into());
+            self.key_states
+                .write()
+                .map_err(|_| Error::MutexPoisoned)?
+                .insert(id.to_str(), acceptance.state);
+        }
+        for event in acceptance.duplicitous {
+            self.add_duplicious_event(event, id)?;
+        }
+        for rct in &acceptance.escrowed_receipts_nt {
+            self.remove_escrow_nt_receipt(id, rct)?;
+        }
+        for rct in acceptance.receipts_nt {
+            self.add_receipt_nt(rct, id)?;
+        }
+        for vrc in &acceptance.escrowed_receipts_t {
+            self.remove_escrow_t_receipt(id, vrc)?;
+        }
+        for vrc in acceptance.receipts_t {
+            self.add_receipt_t(vrc, id)?;
+        }
+        Ok(())
+    }
+
+    fn get_key_state(&self, id: &IdentifierPrefix) -> Result<Option<IdentifierState>, Error> {
+        Ok(self
+            .key_states
+            .read()
+            .map_err(|_| Error::MutexPoisoned)?
+            .get(&id.to_str())
+            .cloned())
+    }
+
+    fn update_key_state(
+        &self,
+        id: &IdentifierPrefix,
+        state: &IdentifierState,
+    ) -> Result<(), Error> {
+        // the KEL can't change while it's read locked
+        let kels = self.key_event_logs.read()?;
+        let kel = kels.get(&id.to_str()).into_iter().flatten();
+        if is_head_state(state, kel.map(|event| &event.signed_event_message)) {
+            self.key_states
+                .write()
+                .map_err(|_| Error::MutexPoisoned)?
+                .insert(id.to_str(), state.clone());
+        }
+        Ok(())
+    }
+
+    fn remove_key_state(&self, id: &IdentifierPrefix) -> Result<(), Error> {
+        self.key_states
+            .write()
+            .map_err(|_| Error::MutexPoisoned)?
+            .remove(&id.to_str());
+        Ok(())
+    }
+
+    fn add_receipt_t(
+        &self,
+        receipt: SignedTransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        self.receipts_t.push(id, receipt)
+    }
+
+    fn get_receipts_t(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedTransferableReceipt>>, Error> {
+        self.receipts_t.iter_values(id)
+    }
+
+    fn add_receipt_nt(
+        &self,
+        receipt: SignedNontransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        self.receipts_nt.push(id, receipt)
+    }
+
+    fn get_receipts_nt(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedNontransferableReceipt>>, Error> {
+        self.receipts_nt.iter_values(id)
+    }
+
+    fn remove_receipts_nt(&self, id: &IdentifierPrefix) -> Result<(), Error> {
+        if self.receipts_nt.iter_values(id)?.is_some() {
+            self.receipts_nt.put(id, vec![])?;
+        }
+        Ok(())
+    }
+
+    fn add_escrow_t_receipt(
+        &self,
+        receipt: SignedTransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        self.escrowed_receipts_t.push(id, receipt)
+    }
+
+    fn get_escrow_t_receipts(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedTransferableReceipt>>, Error> {
+        self.escrowed_receipts_t.iter_values(id)
+    }
+
+    fn remove_escrow_t_receipt(
+        &self,
+        id: &IdentifierPrefix,
+        receipt: &SignedTransferableReceipt,
+    ) -> Result<(), Error> {
+        self.escrowed_receipts_t.remove(id, receipt)
+    }
+
+    fn add_escrow_nt_receipt(
+        &self,
+        receipt: SignedNontransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        self.escrowed_receipts_nt.push(id, receipt)
+    }
+
+    fn get_escrow_nt_receipts(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedNontransferableReceipt>>, Error> {
+        self.escrowed_receipts_nt.iter_values(id)
+    }
+
+    fn remove_escrow_nt_receipt(
+        &self,
+        id: &IdentifierPrefix,
+        receipt: &SignedNontransferableReceipt,
+    ) -> Result<(), Error> {
+        self.escrowed_receipts_nt.remove(id, receipt)
+    }
+
+    fn add_likely_duplicious_event(
+        &self,
+        event: EventMessage<KeyEvent>,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        self.likely_duplicious_events.push(id, event.into())
+    }
+
+    fn get_likely_duplicitous_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedEventMessage>>, Error> {
+        self.likely_duplicious_events.iter_values(id)
+    }
+
+    fn add_duplicious_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        self.duplicitous_events.push(id, event.into())
+    }
+
+    fn get_duplicious_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.duplicitous_events.iter_values(id)
+    }
+
+    fn add_out_of_order_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        self.out_of_order_events.push_unique(id, event.into())
+    }
+
+    fn get_out_of_order_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.out_of_order_events.iter_values(id)
+    }
+
+    fn remove_out_of_order_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        self.out_of_order_events.remove(id, &event.into())
+    }
+
+    fn add_partially_signed_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        // merged signatures replace the ones escrowed for the same event
+        let digest = event.event_message.get_digest();
+        self.partially_signed_events
+            .replace(id, event.into(), |escrowed| {
+                escrowed.signed_event_message.event_message.get_digest() == digest
+            })
+    }
+
+    fn get_partially_signed_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.partially_signed_events.iter_values(id)
+    }
+
+    fn remove_partially_signed_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        self.partially_signed_events.remove(id, &event.into())
+    }
+
+    fn add_partially_witnessed_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        self.partially_witnessed_events
+            .push_unique(id, event.into())
+    }
+
+    fn get_partially_witnessed_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.partially_witnessed_events.iter_values(id)
+    }
+
+    fn remove_partially_witnessed_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        self.partially_witnessed_events.remove(id, &event.into())
+    }
+
+    #[cfg(feature = "query")]
+    fn update_accepted_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error> {
+        let rpys = self
+            .accepted_rpy
+            .iter_values(id)?
+            .into_iter()
+            .flatten()
+            .filter(|s| s.reply.event.get_route() != rpy.reply.event.get_route())
+            .chain(Some(rpy.clone()))
+            .collect();
+        self.accepted_rpy.put(id, rpys)
+    }
+
+    #[cfg(feature = "query")]
+    fn get_accepted_replys(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedReply>>, Error> {
+        self.accepted_rpy.iter_values(id)
+    }
+
+    #[cfg(feature = "query")]
+    fn remove_accepted_reply(&self, id: &IdentifierPrefix, rpy: SignedReply) -> Result<(), Error> {
+        self.accepted_rpy.remove(id, &rpy)
+    }
+
+    #[cfg(feature = "query")]
+    fn add_escrowed_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error> {
+        self.escrowed_replys.push(id, rpy)
+    }
+
+    #[cfg(feature = "query")]
+    fn get_escrowed_replys(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedReply>>, Error> {
+        self.escrowed_replys.iter_values(id)
+    }
+
+    #[cfg(feature = "query")]
+    fn remove_escrowed_reply(&self, id: &IdentifierPrefix, rpy: SignedReply) -> Result<(), Error> {
+        self.escrowed_replys.remove(id, &rpy)
+    }
+
+    #[cfg(feature = "query")]
+    fn get_all_escrowed_replys(&self) -> Result<Option<DbIter<SignedReply>>, Error> {
+        self.escrowed_replys.get_all()
+    }
+}
@@ -1,4 +1,14 @@
 use crate::{
+    error::Error,
+    event::{event_data::EventData, sections::seal::EventSeal, EventMessage},
+    event_message::{
+        key_event_message::KeyEvent,
+        signed_event_message::{
+            SignedEventMessage, SignedNontransferableReceipt, SignedTransferableReceipt,
+            TimestampedSignedEventMessage,
+        },
+        TimestampedEventMessage,
+    },
     event_parsing::message::message,
     prefix::{
         AttachedSignaturePrefix, BasicPrefix, IdentifierPrefix, SelfAddressingPrefix,
@@ -6,11 +16,21 @@ use crate::{
     },
     state::IdentifierState,
 };
+
+#[cfg(feature = "query")]
+use crate::query::reply::SignedReply;
+
 #[cfg(feature = "lmdb")]
 pub mod lmdb;
 
+pub mod memory;
+
 #[cfg(feature = "sled-db")]
 pub mod sled;
+
+#[cfg(feature = "sqlite-db")]
+pub mod sqlite;
+
 /// Event Database
 ///
 /// An Abstract model of state for Key Events,
@@ -174,12 +194,13 @@ pub trait EventDatabase {
 
     /// Add Transferrable Receipt
     ///
-    /// Associates a signature Sig made by Signer with the event referenced by Dig and Pref
+    /// Associates a signature Sig made with the keys of the validator
+    /// establishment event referenced by Seal with the event referenced by Dig and Pref
     fn add_t_receipt_for_event(
         &self,
         pref: &IdentifierPrefix,
         dig: &SelfAddressingPrefix,
-        signer: &IdentifierPrefix,
+        validator_seal: &EventSeal,
         sig: &AttachedSignaturePrefix,
     ) -> Result<(), Self::Error>;
 
@@ -197,7 +218,7 @@ pub trait EventDatabase {
         &self,
         pref: &IdentifierPrefix,
         dig: &SelfAddressingPrefix,
-        signer: &IdentifierPrefix,
+        validator_seal: &EventSeal,
         sig: &AttachedSignaturePrefix,
     ) -> Result<(), Self::Error>;
 
@@ -209,6 +230,336 @@ pub trait EventDatabase {
     ) -> Result<bool, Self::Error>;
 }
 
+/// Values stored for an identifier, in the order they were added
+pub type DbIter<T> = std::vec::IntoIter<T>;
+
+/// Event Acceptance
+///
+/// Everything written when a verified event is accepted into the KEL of
+/// its identifier, applied by `EventStorage::accept_event`.
+pub struct EventAcceptance {
+    /// Event appended to the KEL
+    pub event: SignedEventMessage,
+    /// Key state of the identifier with the event applied
+    pub state: IdentifierState,
+    /// KEL events the event replaces, removed from the KEL
+    pub superseded: Vec<SignedEventMessage>,
+    /// Events to record as duplicitous
+    pub duplicitous: Vec<SignedEventMessage>,
+    /// Escrowed receipts of the event, removed from the escrow
+    pub escrowed_receipts_nt: Vec<SignedNontransferableReceipt>,
+    pub escrowed_receipts_t: Vec<SignedTransferableReceipt>,
+    /// Valid receipts of the event to store
+    pub receipts_nt: Vec<SignedNontransferableReceipt>,
+    pub receipts_t: Vec<SignedTransferableReceipt>,
+}
+
+impl EventAcceptance {
+    pub fn new(event: SignedEventMessage, state: IdentifierState) -> Self {
+        Self {
+            event,
+            state,
+            superseded: vec![],
+            duplicitous: vec![],
+            escrowed_receipts_nt: vec![],
+            escrowed_receipts_t: vec![],
+            receipts_nt: vec![],
+            receipts_t: vec![],
+        }
+    }
+
+    /// Whether the event still follows the head of `kel`
+    ///
+    /// With the superseded events taken out, the KEL has to end with the
+    /// prior event of the accepted one, or be empty for an inception.
+    /// Otherwise the KEL changed since the event was validated, so storages
+    /// check it together with the writes of `EventStorage::accept_event`.
+    pub fn extends<'a>(&self, kel: impl IntoIterator<Item = &'a SignedEventMessage>) -> bool {
+        let event = &self.event.event_message.event;
+        let head = kel
+            .into_iter()
+            .filter(|kel_event| !self.superseded.contains(kel_event))
+            .max_by_key(|kel_event| kel_event.event_message.event.get_sn());
+        let prior = match event.get_event_data() {
+            EventData::Icp(_) | EventData::Dip(_) => None,
+            EventData::Rot(rot) | EventData::Drt(rot) => Some(rot.previous_event_hash),
+            EventData::Ixn(ixn) => Some(ixn.previous_event_hash),
+        };
+        match (head, prior) {
+            (None, None) => true,
+            (Some(head), Some(prior)) => {
+                head.event_message.event.get_sn() + 1 == event.get_sn()
+                    && head.event_message.get_digest() == prior
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether `state` is the key state at the head of `kel`, the only one
+/// storages cache
+pub(crate) fn is_head_state<'a>(
+    state: &IdentifierState,
+    kel: impl IntoIterator<Item = &'a SignedEventMessage>,
+) -> bool {
+    let head = kel
+        .into_iter()
+        .max_by_key(|event| event.event_message.event.get_sn());
+    matches!(head, Some(head) if head.event_message.event.get_sn() == state.sn
+        && head.event_message.get_digest() == state.last_event_digest)
+}
+
+/// Event Storage
+///
+/// Storage backing the `EventProcessor`: finalized KELs and their
+/// key states, receipts, escrows, duplicitous events and replies,
+/// each kept per identifier.
+pub trait EventStorage {
+    fn add_kel_finalized_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error>;
+
+    fn get_kel_finalized_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error>;
+
+    fn remove_kel_finalized_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error>;
+
+    /// Accept Event
+    ///
+    /// Applies all the writes of `acceptance` and caches its key state.
+    /// Fails with `Error::KelChangedError` unless `acceptance` extends the
+    /// stored KEL.
+    ///
+    /// The only guarantee every backend has to give is that the KEL itself
+    /// changes at once and only if it still is the one `acceptance` was
+    /// validated against, so processors sharing the storage can't fork a
+    /// KEL. Backends able to also write the other records in the same
+    /// transaction, so the KEL is never seen without them. Others write
+    /// them after the KEL, where a failure leaves the event accepted
+    /// without some of its receipts or duplicity records. The key state
+    /// cache is optional, see `EventStorage::get_key_state`.
+    ///
+    /// The default checks the KEL first and then applies the writes one
+    /// after another, which doesn't guard against concurrent writers.
+    fn accept_event(&self, acceptance: EventAcceptance) -> Result<(), Error> {
+        let id = &acceptance.event.event_message.event.get_prefix();
+        let kel = self
+            .get_kel_finalized_events(id)?
+            .into_iter()
+            .flatten()
+            .map(|event| event.signed_event_message)
+            .collect::<Vec<_>>();
+        if !acceptance.extends(&kel) {
+            return Err(Error::KelChangedError);
+        }
+        for event in &acceptance.superseded {
+            self.remove_kel_finalized_event(id, event)?;
+        }
+        self.add_kel_finalized_event(acceptance.event, id)?;
+        for event in acceptance.duplicitous {
+            self.add_duplicious_event(event, id)?;
+        }
+        for rct in &acceptance.escrowed_receipts_nt {
+            self.remove_escrow_nt_receipt(id, rct)?;
+        }
+        for rct in acceptance.receipts_nt {
+            self.add_receipt_nt(rct, id)?;
+        }
+        for vrc in &acceptance.escrowed_receipts_t {
+            self.remove_escrow_t_receipt(id, vrc)?;
+        }
+        for vrc in acceptance.receipts_t {
+            self.add_receipt_t(vrc, id)?;
+        }
+        self.update_key_state(id, &acceptance.state)
+    }
+
+    /// Key state of the identifier cached since its KEL last changed
+    ///
+    /// Adding or removing KEL events must remove the cached state. Backends
+    /// without a cache return `None` and ignore `update_key_state`, the
+    /// state is then computed from the KEL.
+    fn get_key_state(&self, id: &IdentifierPrefix) -> Result<Option<IdentifierState>, Error>;
+
+    /// Caches `state` if it's still the key state at the head of the KEL
+    ///
+    /// A state replayed while the KEL changed is dropped, checked within
+    /// the same transaction as the write.
+    fn update_key_state(&self, id: &IdentifierPrefix, state: &IdentifierState)
+        -> Result<(), Error>;
+
+    fn remove_key_state(&self, id: &IdentifierPrefix) -> Result<(), Error>;
+
+    fn add_receipt_t(
+        &self,
+        receipt: SignedTransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error>;
+
+    fn get_receipts_t(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedTransferableReceipt>>, Error>;
+
+    fn add_receipt_nt(
+        &self,
+        receipt: SignedNontransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error>;
+
+    fn get_receipts_nt(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedNontransferableReceipt>>, Error>;
+
+    fn remove_receipts_nt(&self, id: &IdentifierPrefix) -> Result<(), Error>;
+
+    fn add_escrow_t_receipt(
+        &self,
+        receipt: SignedTransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error>;
+
+    fn get_escrow_t_receipts(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedTransferableReceipt>>, Error>;
+
+    fn remove_escrow_t_receipt(
+        &self,
+        id: &IdentifierPrefix,
+        receipt: &SignedTransferableReceipt,
+    ) -> Result<(), Error>;
+
+    fn add_escrow_nt_receipt(
+        &self,
+        receipt: SignedNontransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error>;
+
+    fn get_escrow_nt_receipts(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedNontransferableReceipt>>, Error>;
+
+    fn remove_escrow_nt_receipt(
+        &self,
+        id: &IdentifierPrefix,
+        receipt: &SignedNontransferableReceipt,
+    ) -> Result<(), Error>;
+
+    fn add_likely_duplicious_event(
+        &self,
+        event: EventMessage<KeyEvent>,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error>;
+
+    fn get_likely_duplicitous_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedEventMessage>>, Error>;
+
+    fn add_duplicious_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error>;
+
+    fn get_duplicious_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error>;
+
+    /// Escrows the event unless it's already escrowed
+    fn add_out_of_order_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error>;
+
+    fn get_out_of_order_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error>;
+
+    fn remove_out_of_order_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error>;
+
+    fn add_partially_signed_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error>;
+
+    fn get_partially_signed_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error>;
+
+    fn remove_partially_signed_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error>;
+
+    /// Escrows the event unless it's already escrowed
+    fn add_partially_witnessed_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error>;
+
+    fn get_partially_witnessed_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error>;
+
+    fn remove_partially_witnessed_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error>;
+
+    /// Stores the reply in place of the accepted one with the same route
+    #[cfg(feature = "query")]
+    fn update_accepted_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error>;
+
+    #[cfg(feature = "query")]
+    fn get_accepted_replys(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedReply>>, Error>;
+
+    #[cfg(feature = "query")]
+    fn remove_accepted_reply(&self, id: &IdentifierPrefix, rpy: SignedReply) -> Result<(), Error>;
+
+    #[cfg(feature = "query")]
+    fn add_escrowed_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error>;
+
+    #[cfg(feature = "query")]
+    fn get_escrowed_replys(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedReply>>, Error>;
+
+    #[cfg(feature = "query")]
+    fn remove_escrowed_reply(&self, id: &IdentifierPrefix, rpy: SignedReply) -> Result<(), Error>;
+
+    #[cfg(feature = "query")]
+    fn get_all_escrowed_replys(&self) -> Result<Option<DbIter<SignedReply>>, Error>;
+}
+
 // #[cfg(test)]
 // fn test_db<D: EventDatabase>(db: D) -> Result<(), D::Error> {
 //     use crate::{
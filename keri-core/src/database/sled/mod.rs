@@ -1,6 +1,7 @@
 mod tables;
 
 use crate::{
+    database::{is_head_state, DbIter, EventAcceptance, EventStorage},
     error::Error,
     event::EventMessage,
     event_message::{
@@ -12,6 +13,11 @@ use crate::{
         TimestampedEventMessage,
     },
     prefix::IdentifierPrefix,
+    state::IdentifierState,
+};
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError},
+    Transactional,
 };
 use std::path::Path;
 use tables::{SledEventTree, SledEventTreeVec};
@@ -25,10 +31,18 @@ pub struct SledEventDatabase {
     identifiers: SledEventTree<IdentifierPrefix>,
     // "kels" tree
     key_event_logs: SledEventTreeVec<TimestampedSignedEventMessage>,
+    // "ksts" tree
+    key_states: SledEventTree<IdentifierState>,
     // "ldes" tree
     likely_duplicious_events: SledEventTreeVec<TimestampedEventMessage>,
     // "dels" tree
     duplicitous_events: SledEventTreeVec<TimestampedSignedEventMessage>,
+    // "ooes" tree
+    out_of_order_events: SledEventTreeVec<TimestampedSignedEventMessage>,
+    // "pses" tree
+    partially_signed_events: SledEventTreeVec<TimestampedSignedEventMessage>,
+    // "pwes" tree
+    partially_witnessed_events: SledEventTreeVec<TimestampedSignedEventMessage>,
     // "rcts" tree
     receipts_nt: SledEventTreeVec<SignedNontransferableReceipt>,
     // "ures" tree
@@ -58,8 +72,12 @@ impl SledEventDatabase {
             escrowed_receipts_t: SledEventTreeVec::new(db.open_tree(b"vres")?),
             receipts_nt: SledEventTreeVec::new(db.open_tree(b"rcts")?),
             key_event_logs: SledEventTreeVec::new(db.open_tree(b"kels")?),
+            key_states: SledEventTree::new(db.open_tree(b"ksts")?),
             likely_duplicious_events: SledEventTreeVec::new(db.open_tree(b"ldes")?),
             duplicitous_events: SledEventTreeVec::new(db.open_tree(b"dels")?),
+            out_of_order_events: SledEventTreeVec::new(db.open_tree(b"ooes")?),
+            partially_signed_events: SledEventTreeVec::new(db.open_tree(b"pses")?),
+            partially_witnessed_events: SledEventTreeVec::new(db.open_tree(b"pwes")?),
             #[cfg(feature = "query")]
             accepted_rpy: SledEventTreeVec::new(db.open_tree(b"knas")?),
             #[cfg(feature = "query")]
@@ -67,33 +85,126 @@ impl SledEventDatabase {
         })
     }
 
-    pub fn add_kel_finalized_event(
+    /// Identifiers known to the database
+    pub fn get_identifiers(&self) -> impl DoubleEndedIterator<Item = IdentifierPrefix> {
+        self.identifiers.iter()
+    }
+}
+
+impl EventStorage for SledEventDatabase {
+    fn add_kel_finalized_event(
         &self,
         event: SignedEventMessage,
         id: &IdentifierPrefix,
     ) -> Result<(), Error> {
         self.key_event_logs
-            .push(self.identifiers.designated_key(id), event.into())
+            .push(self.identifiers.designated_key(id), event.into())?;
+        self.remove_key_state(id)
     }
 
-    pub fn get_kel_finalized_events(
+    fn get_kel_finalized_events(
         &self,
         id: &IdentifierPrefix,
-    ) -> Option<impl DoubleEndedIterator<Item = TimestampedSignedEventMessage>> {
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
         self.key_event_logs
             .iter_values(self.identifiers.designated_key(id))
     }
 
-    pub fn remove_kel_finalized_event(
+    fn remove_kel_finalized_event(
         &self,
         id: &IdentifierPrefix,
         event: &SignedEventMessage,
     ) -> Result<(), Error> {
         self.key_event_logs
-            .remove(self.identifiers.designated_key(id), &event.into())
+            .remove(self.identifiers.designated_key(id), &event.into())?;
+        self.remove_key_state(id)
+    }
+
+    /// Accept Event
+    ///
+    /// Applies `acceptance` in a single transaction over the trees it
+    /// touches, which also reads the KEL to check the acceptance against
+    /// it. The key of the identifier is resolved beforehand, as
+    /// transactions can't scan the identifiers tree. That may register the
+    /// identifier, which is idempotent and serialized, so all an aborted
+    /// transaction can leave behind is an identifier without values, read
+    /// the same as an unknown one.
+    fn accept_event(&self, acceptance: EventAcceptance) -> Result<(), Error> {
+        let key = self
+            .identifiers
+            .designated_key(&acceptance.event.event_message.event.get_prefix());
+        (
+            self.key_event_logs.tree(),
+            self.key_states.tree(),
+            self.duplicitous_events.tree(),
+            self.receipts_nt.tree(),
+            self.escrowed_receipts_nt.tree(),
+            self.receipts_t.tree(),
+            self.escrowed_receipts_t.tree(),
+        )
+            .transaction(|(kels, ksts, dels, rcts, ures, vrcs, vres)| {
+                let kel = self.key_event_logs.get_in(kels, key)?.unwrap_or_default();
+                if !acceptance.extends(kel.iter().map(|event| &event.signed_event_message)) {
+                    return Err(ConflictableTransactionError::Abort(Error::KelChangedError));
+                }
+                for event in &acceptance.superseded {
+                    self.key_event_logs.remove_in(kels, key, &event.into())?;
+                }
+                self.key_event_logs
+                    .push_in(kels, key, acceptance.event.clone().into())?;
+                for event in &acceptance.duplicitous {
+                    self.duplicitous_events
+                        .push_in(dels, key, event.clone().into())?;
+                }
+                for rct in &acceptance.escrowed_receipts_nt {
+                    self.escrowed_receipts_nt.remove_in(ures, key, rct)?;
+                }
+                for rct in &acceptance.receipts_nt {
+                    self.receipts_nt.push_in(rcts, key, rct.clone())?;
+                }
+                for vrc in &acceptance.escrowed_receipts_t {
+                    self.escrowed_receipts_t.remove_in(vres, key, vrc)?;
+                }
+                for vrc in &acceptance.receipts_t {
+                    self.receipts_t.push_in(vrcs, key, vrc.clone())?;
+                }
+                self.key_states.insert_in(ksts, key, &acceptance.state)
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(e) => e,
+                TransactionError::Storage(e) => e.into(),
+            })
+    }
+
+    fn get_key_state(&self, id: &IdentifierPrefix) -> Result<Option<IdentifierState>, Error> {
+        self.key_states.get(self.identifiers.designated_key(id))
     }
 
-    pub fn add_receipt_t(
+    fn update_key_state(
+        &self,
+        id: &IdentifierPrefix,
+        state: &IdentifierState,
+    ) -> Result<(), Error> {
+        let key = self.identifiers.designated_key(id);
+        (self.key_event_logs.tree(), self.key_states.tree())
+            .transaction(|(kels, ksts)| {
+                let kel = self.key_event_logs.get_in(kels, key)?.unwrap_or_default();
+                if is_head_state(state, kel.iter().map(|event| &event.signed_event_message)) {
+                    self.key_states.insert_in(ksts, key, state)?;
+                }
+                Ok(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(e) => e,
+                TransactionError::Storage(e) => e.into(),
+            })
+    }
+
+    fn remove_key_state(&self, id: &IdentifierPrefix) -> Result<(), Error> {
+        self.key_states.remove(self.identifiers.designated_key(id))
+    }
+
+    fn add_receipt_t(
         &self,
         receipt: SignedTransferableReceipt,
         id: &IdentifierPrefix,
@@ -102,15 +213,15 @@ impl SledEventDatabase {
             .push(self.identifiers.designated_key(id), receipt)
     }
 
-    pub fn get_receipts_t(
+    fn get_receipts_t(
         &self,
         id: &IdentifierPrefix,
-    ) -> Option<impl DoubleEndedIterator<Item = SignedTransferableReceipt>> {
+    ) -> Result<Option<DbIter<SignedTransferableReceipt>>, Error> {
         self.receipts_t
             .iter_values(self.identifiers.designated_key(id))
     }
 
-    pub fn add_receipt_nt(
+    fn add_receipt_nt(
         &self,
         receipt: SignedNontransferableReceipt,
         id: &IdentifierPrefix,
@@ -119,16 +230,16 @@ impl SledEventDatabase {
             .push(self.identifiers.designated_key(id), receipt)
     }
 
-    pub fn get_receipts_nt(
+    fn get_receipts_nt(
         &self,
         id: &IdentifierPrefix,
-    ) -> Option<impl DoubleEndedIterator<Item = SignedNontransferableReceipt>> {
+    ) -> Result<Option<DbIter<SignedNontransferableReceipt>>, Error> {
         self.receipts_nt
             .iter_values(self.identifiers.designated_key(id))
     }
 
-    pub fn remove_receipts_nt(&self, id: &IdentifierPrefix) -> Result<(), Error> {
-        if let Some(receipts) = self.get_receipts_nt(id) {
+    fn remove_receipts_nt(&self, id: &IdentifierPrefix) -> Result<(), Error> {
+        if let Some(receipts) = self.get_receipts_nt(id)? {
             for receipt in receipts {
                 self.receipts_nt
                     .remove(self.identifiers.designated_key(id), &receipt)?;
@@ -137,7 +248,7 @@ impl SledEventDatabase {
         Ok(())
     }
 
-    pub fn add_escrow_t_receipt(
+    fn add_escrow_t_receipt(
         &self,
         receipt: SignedTransferableReceipt,
         id: &IdentifierPrefix,
@@ -146,15 +257,15 @@ impl SledEventDatabase {
             .push(self.identifiers.designated_key(id), receipt)
     }
 
-    pub fn get_escrow_t_receipts(
+    fn get_escrow_t_receipts(
         &self,
         id: &IdentifierPrefix,
-    ) -> Option<impl DoubleEndedIterator<Item = SignedTransferableReceipt>> {
+    ) -> Result<Option<DbIter<SignedTransferableReceipt>>, Error> {
         self.escrowed_receipts_t
             .iter_values(self.identifiers.designated_key(id))
     }
 
-    pub fn remove_escrow_t_receipt(
+    fn remove_escrow_t_receipt(
         &self,
         id: &IdentifierPrefix,
         receipt: &SignedTransferableReceipt,
@@ -163,7 +274,7 @@ impl SledEventDatabase {
             .remove(self.identifiers.designated_key(id), receipt)
     }
 
-    pub fn add_escrow_nt_receipt(
+    fn add_escrow_nt_receipt(
         &self,
         receipt: SignedNontransferableReceipt,
         id: &IdentifierPrefix,
@@ -172,15 +283,15 @@ impl SledEventDatabase {
             .push(self.identifiers.designated_key(id), receipt)
     }
 
-    pub fn get_escrow_nt_receipts(
+    fn get_escrow_nt_receipts(
         &self,
         id: &IdentifierPrefix,
-    ) -> Option<impl DoubleEndedIterator<Item = SignedNontransferableReceipt>> {
+    ) -> Result<Option<DbIter<SignedNontransferableReceipt>>, Error> {
         self.escrowed_receipts_nt
             .iter_values(self.identifiers.designated_key(id))
     }
 
-    pub fn remove_escrow_nt_receipt(
+    fn remove_escrow_nt_receipt(
         &self,
         id: &IdentifierPrefix,
         receipt: &SignedNontransferableReceipt,
@@ -189,7 +300,7 @@ impl SledEventDatabase {
             .remove(self.identifiers.designated_key(id), receipt)
     }
 
-    pub fn add_likely_duplicious_event(
+    fn add_likely_duplicious_event(
         &self,
         event: EventMessage<KeyEvent>,
         id: &IdentifierPrefix,
@@ -198,15 +309,15 @@ impl SledEventDatabase {
             .push(self.identifiers.designated_key(id), event.into())
     }
 
-    pub fn get_likely_duplicitous_events(
+    fn get_likely_duplicitous_events(
         &self,
         id: &IdentifierPrefix,
-    ) -> Option<impl DoubleEndedIterator<Item = TimestampedEventMessage>> {
+    ) -> Result<Option<DbIter<TimestampedEventMessage>>, Error> {
         self.likely_duplicious_events
             .iter_values(self.identifiers.designated_key(id))
     }
 
-    pub fn add_duplicious_event(
+    fn add_duplicious_event(
         &self,
         event: SignedEventMessage,
         id: &IdentifierPrefix,
@@ -215,23 +326,110 @@ impl SledEventDatabase {
             .push(self.identifiers.designated_key(id), event.into())
     }
 
-    pub fn get_duplicious_events(
+    fn get_duplicious_events(
         &self,
         id: &IdentifierPrefix,
-    ) -> Option<impl DoubleEndedIterator<Item = TimestampedSignedEventMessage>> {
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
         self.duplicitous_events
             .iter_values(self.identifiers.designated_key(id))
     }
 
-    #[cfg(feature = "query")]
-    pub fn update_accepted_reply(
+    fn add_out_of_order_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        let event = TimestampedSignedEventMessage::new(event);
+        let key = self.identifiers.designated_key(id);
+        match self.out_of_order_events.get(key)? {
+            Some(escrowed) if escrowed.contains(&event) => Ok(()),
+            _ => self.out_of_order_events.push(key, event),
+        }
+    }
+
+    fn get_out_of_order_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.out_of_order_events
+            .iter_values(self.identifiers.designated_key(id))
+    }
+
+    fn remove_out_of_order_event(
         &self,
-        rpy: SignedReply,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        self.out_of_order_events
+            .remove(self.identifiers.designated_key(id), &event.into())
+    }
+
+    fn add_partially_signed_event(
+        &self,
+        event: SignedEventMessage,
         id: &IdentifierPrefix,
     ) -> Result<(), Error> {
+        // merged signatures replace the ones escrowed for the same event
+        let key = self.identifiers.designated_key(id);
+        let digest = event.event_message.get_digest();
+        let mut escrowed = self.partially_signed_events.get(key)?.unwrap_or_default();
+        escrowed.retain(|e| e.signed_event_message.event_message.get_digest() != digest);
+        escrowed.push(event.into());
+        self.partially_signed_events.put(key, escrowed)
+    }
+
+    fn get_partially_signed_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.partially_signed_events
+            .iter_values(self.identifiers.designated_key(id))
+    }
+
+    fn remove_partially_signed_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        self.partially_signed_events
+            .remove(self.identifiers.designated_key(id), &event.into())
+    }
+
+    fn add_partially_witnessed_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        let event = TimestampedSignedEventMessage::new(event);
+        let key = self.identifiers.designated_key(id);
+        match self.partially_witnessed_events.get(key)? {
+            Some(escrowed) if escrowed.contains(&event) => Ok(()),
+            _ => self.partially_witnessed_events.push(key, event),
+        }
+    }
+
+    fn get_partially_witnessed_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.partially_witnessed_events
+            .iter_values(self.identifiers.designated_key(id))
+    }
+
+    fn remove_partially_witnessed_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        self.partially_witnessed_events
+            .remove(self.identifiers.designated_key(id), &event.into())
+    }
+
+    #[cfg(feature = "query")]
+    fn update_accepted_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error> {
         match self
             .accepted_rpy
-            .iter_values(self.identifiers.designated_key(id))
+            .iter_values(self.identifiers.designated_key(id))?
         {
             Some(rpys) => {
                 let filtered = rpys
@@ -248,51 +446,43 @@ impl SledEventDatabase {
     }
 
     #[cfg(feature = "query")]
-    pub fn get_accepted_replys(
+    fn get_accepted_replys(
         &self,
         id: &IdentifierPrefix,
-    ) -> Option<impl DoubleEndedIterator<Item = SignedReply>> {
+    ) -> Result<Option<DbIter<SignedReply>>, Error> {
         self.accepted_rpy
             .iter_values(self.identifiers.designated_key(id))
     }
 
     #[cfg(feature = "query")]
-    pub fn remove_accepted_reply(
-        &self,
-        id: &IdentifierPrefix,
-        rpy: SignedReply,
-    ) -> Result<(), Error> {
+    fn remove_accepted_reply(&self, id: &IdentifierPrefix, rpy: SignedReply) -> Result<(), Error> {
         self.accepted_rpy
             .remove(self.identifiers.designated_key(id), &rpy)
     }
 
     #[cfg(feature = "query")]
-    pub fn add_escrowed_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error> {
+    fn add_escrowed_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error> {
         self.escrowed_replys
             .push(self.identifiers.designated_key(id), rpy)
     }
 
     #[cfg(feature = "query")]
-    pub fn get_escrowed_replys(
+    fn get_escrowed_replys(
         &self,
         id: &IdentifierPrefix,
-    ) -> Option<impl DoubleEndedIterator<Item = SignedReply>> {
+    ) -> Result<Option<DbIter<SignedReply>>, Error> {
         self.escrowed_replys
             .iter_values(self.identifiers.designated_key(id))
     }
 
     #[cfg(feature = "query")]
-    pub fn remove_escrowed_reply(
-        &self,
-        id: &IdentifierPrefix,
-        rpy: SignedReply,
-    ) -> Result<(), Error> {
+    fn remove_escrowed_reply(&self, id: &IdentifierPrefix, rpy: SignedReply) -> Result<(), Error> {
         self.escrowed_replys
             .remove(self.identifiers.designated_key(id), &rpy)
     }
 
     #[cfg(feature = "query")]
-    pub fn get_all_escrowed_replys(&self) -> Option<impl DoubleEndedIterator<Item = SignedReply>> {
+    fn get_all_escrowed_replys(&self) -> Result<Option<DbIter<SignedReply>>, Error> {
         self.escrowed_replys.get_all()
     }
 }
@@ -2,7 +2,10 @@
 use crate::error::Error;
 use arrayref::array_ref;
 use serde::{de::DeserializeOwned, Serialize};
-use std::marker::PhantomData;
+use sled::transaction::{
+    ConflictableTransactionError, ConflictableTransactionResult, TransactionalTree,
+};
+use std::{marker::PhantomData, sync::Mutex};
 
 /// Imitates collection table per key
 ///
@@ -103,26 +106,81 @@ where
 
     /// iterate inner collection under same key
     ///
-    pub fn iter_values(&self, key: u64) -> Option<impl DoubleEndedIterator<Item = T>> {
-        if let Ok(Some(values)) = self.tree.get(key_bytes(key)) {
-            Some(
-                serde_cbor::from_slice::<Vec<T>>(&values)
-                    .unwrap()
-                    .into_iter(),
-            )
-        } else {
-            None
+    pub fn iter_values(&self, key: u64) -> Result<Option<std::vec::IntoIter<T>>, Error> {
+        match self.tree.get(key_bytes(key))? {
+            Some(values) => Ok(Some(serde_cbor::from_slice::<Vec<T>>(&values)?.into_iter())),
+            None => Ok(None),
         }
     }
 
-    pub fn get_all(&self) -> Option<impl DoubleEndedIterator<Item = T>> {
-        Some(
-            self.tree
-                .into_iter()
-                .flatten()
-                .map(|(_key, values)| serde_cbor::from_slice::<Vec<T>>(&values).unwrap())
-                .flatten(),
-        )
+    /// underlying tree, to open transactions over it
+    ///
+    pub fn tree(&self) -> &sled::Tree {
+        &self.tree
+    }
+
+    /// `get()` within a transaction over the tree
+    ///
+    pub fn get_in(
+        &self,
+        tree: &TransactionalTree,
+        key: u64,
+    ) -> ConflictableTransactionResult<Option<Vec<T>>, Error> {
+        match tree.get(key_bytes(key))? {
+            Some(v) => Ok(Some(serde_cbor::from_slice(&v).map_err(abort)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// `put()` within a transaction over the tree
+    ///
+    pub fn put_in(
+        &self,
+        tree: &TransactionalTree,
+        key: u64,
+        value: Vec<T>,
+    ) -> ConflictableTransactionResult<(), Error> {
+        tree.insert(&key_bytes(key), serde_cbor::to_vec(&value).map_err(abort)?)?;
+        Ok(())
+    }
+
+    /// `push()` within a transaction over the tree
+    ///
+    pub fn push_in(
+        &self,
+        tree: &TransactionalTree,
+        key: u64,
+        value: T,
+    ) -> ConflictableTransactionResult<(), Error> {
+        let mut set = self.get_in(tree, key)?.unwrap_or_default();
+        set.push(value);
+        self.put_in(tree, key, set)
+    }
+
+    /// `remove()` within a transaction over the tree
+    ///
+    pub fn remove_in(
+        &self,
+        tree: &TransactionalTree,
+        key: u64,
+        value: &T,
+    ) -> ConflictableTransactionResult<(), Error>
+    where
+        T: PartialEq,
+    {
+        match self.get_in(tree, key)? {
+            Some(set) => self.put_in(tree, key, set.into_iter().filter(|e| e != value).collect()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_all(&self) -> Result<Option<std::vec::IntoIter<T>>, Error> {
+        let mut all = vec![];
+        for entry in self.tree.iter() {
+            let (_key, values) = entry?;
+            all.append(&mut serde_cbor::from_slice::<Vec<T>>(&values)?);
+        }
+        Ok(Some(all.into_iter()))
     }
 }
 
@@ -130,6 +188,8 @@ where
 ///
 pub(crate) struct SledEventTree<T> {
     tree: sled::Tree,
+    // held while a new value is given a key
+    registration: Mutex<()>,
     marker: PhantomData<T>,
 }
 
@@ -139,6 +199,7 @@ impl<T> SledEventTree<T> {
     pub fn new(tree: sled::Tree) -> Self {
         Self {
             tree,
+            registration: Mutex::new(()),
             marker: PhantomData,
         }
     }
@@ -186,6 +247,31 @@ where
         Ok(())
     }
 
+    /// underlying tree, to open transactions over it
+    ///
+    pub fn tree(&self) -> &sled::Tree {
+        &self.tree
+    }
+
+    /// `insert()` within a transaction over the tree
+    ///
+    pub fn insert_in(
+        &self,
+        tree: &TransactionalTree,
+        key: u64,
+        value: &T,
+    ) -> ConflictableTransactionResult<(), Error> {
+        tree.insert(&key_bytes(key), serde_cbor::to_vec(value).map_err(abort)?)?;
+        Ok(())
+    }
+
+    /// removes the value with given `key` if present
+    ///
+    pub fn remove(&self, key: u64) -> Result<(), Error> {
+        self.tree.remove(key_bytes(key))?;
+        Ok(())
+    }
+
     /// iterator over `T` deserialized from the db
     ///
     pub fn iter(&self) -> impl DoubleEndedIterator<Item = T> {
@@ -223,11 +309,18 @@ where
     /// combination of `get_key_by_value()` and `get_next_key()`
     /// also expensive...
     /// to be used when unsure if identifier is present in the db
+    /// registering it is serialized, so concurrent calls for a new
+    /// identifier all get the same key and different ones never share it
     ///
     pub fn designated_key(&self, identifier: &T) -> u64
     where
         T: Serialize,
     {
+        // only guards the tree, which is never left half updated
+        let _registration = self
+            .registration
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
         if let Ok(Some(key)) = self.get_key_by_value(identifier) {
             key
         } else {
@@ -243,3 +336,9 @@ where
 fn key_bytes(key: u64) -> [u8; 8] {
     key.to_be_bytes()
 }
+
+/// aborts the transaction with the given error
+///
+fn abort<E: Into<Error>>(e: E) -> ConflictableTransactionError<Error> {
+    ConflictableTransactionError::Abort(e.into())
+}
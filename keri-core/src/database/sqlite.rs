@@ -0,0 +1,852 @@
+use std::{
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    database::{is_head_state, DbIter, EventAcceptance, EventStorage},
+    error::Error,
+    event::EventMessage,
+    event_message::{
+        key_event_message::KeyEvent,
+        signed_event_message::{
+            SignedEventMessage, SignedNontransferableReceipt, SignedTransferableReceipt,
+            TimestampedSignedEventMessage,
+        },
+        TimestampedEventMessage,
+    },
+    prefix::{IdentifierPrefix, Prefix},
+    state::IdentifierState,
+};
+
+#[cfg(feature = "query")]
+use crate::query::reply::SignedReply;
+
+/// Schema changes, the version of a database is the number of them applied
+///
+/// Tables are named after the trees of `SledEventDatabase`. They keep a row
+/// per value, next to the sequence number and digest of the event it's
+/// about, and only the key states a single row per identifier. `written`
+/// records the tables an identifier ever had values in, so that like with
+/// Sled, values that were all removed read as empty rather than missing.
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE iids (id INTEGER PRIMARY KEY, prefix TEXT NOT NULL UNIQUE);
+    CREATE TABLE written (identifier INTEGER NOT NULL REFERENCES iids (id), name TEXT NOT NULL, PRIMARY KEY (identifier, name));
+    CREATE TABLE ksts (identifier INTEGER PRIMARY KEY REFERENCES iids (id), value BLOB NOT NULL);
+    CREATE TABLE kels (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE ldes (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE dels (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE ooes (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE pses (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE pwes (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE rcts (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE ures (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE vrcs (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE vres (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE knas (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE TABLE knes (identifier INTEGER NOT NULL REFERENCES iids (id), sn INTEGER NOT NULL, digest TEXT NOT NULL, value BLOB NOT NULL);
+    CREATE INDEX kels_events ON kels (identifier, sn, digest);
+    CREATE INDEX ldes_events ON ldes (identifier, sn, digest);
+    CREATE INDEX dels_events ON dels (identifier, sn, digest);
+    CREATE INDEX ooes_events ON ooes (identifier, sn, digest);
+    CREATE INDEX pses_events ON pses (identifier, sn, digest);
+    CREATE INDEX pwes_events ON pwes (identifier, sn, digest);
+    CREATE INDEX rcts_events ON rcts (identifier, sn, digest);
+    CREATE INDEX ures_events ON ures (identifier, sn, digest);
+    CREATE INDEX vrcs_events ON vrcs (identifier, sn, digest);
+    CREATE INDEX vres_events ON vres (identifier, sn, digest);
+    CREATE INDEX knas_events ON knas (identifier, sn, digest);
+    CREATE INDEX knes_events ON knes (identifier, sn, digest);
+"];
+
+/// Schema version the database is migrated to when opened
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Value kept in a row of its own
+trait EventValue: Serialize + DeserializeOwned + PartialEq {
+    /// Sequence number and digest of the event the value is about
+    fn event(&self) -> (u64, String);
+}
+
+impl EventValue for TimestampedSignedEventMessage {
+    fn event(&self) -> (u64, String) {
+        let event = &self.signed_event_message.event_message;
+        (event.event.get_sn(), event.get_digest().to_str())
+    }
+}
+
+impl EventValue for TimestampedEventMessage {
+    fn event(&self) -> (u64, String) {
+        let event = &self.event_message;
+        (event.event.get_sn(), event.get_digest().to_str())
+    }
+}
+
+impl EventValue for SignedNontransferableReceipt {
+    fn event(&self) -> (u64, String) {
+        let receipt = &self.body.event;
+        (receipt.sn, receipt.receipted_event_digest.to_str())
+    }
+}
+
+impl EventValue for SignedTransferableReceipt {
+    fn event(&self) -> (u64, String) {
+        let receipt = &self.body.event;
+        (receipt.sn, receipt.receipted_event_digest.to_str())
+    }
+}
+
+/// Replies are about the key state they carry
+#[cfg(feature = "query")]
+impl EventValue for SignedReply {
+    fn event(&self) -> (u64, String) {
+        (
+            self.reply.event.get_state().sn,
+            self.reply.get_digest().to_str(),
+        )
+    }
+}
+
+pub struct SqliteEventDatabase {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteEventDatabase {
+    /// Opens the database file at `path`, creating it if needed, and
+    /// migrates its schema to `SCHEMA_VERSION`.
+    pub fn new<'a, P>(path: P) -> Result<Self, Error>
+    where
+        P: Into<&'a Path>,
+    {
+        let mut connection = Connection::open(path.into())?;
+        let tx = connection.transaction()?;
+        tx.execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);")?;
+        let version = tx
+            .query_row("SELECT version FROM schema_version", [], |row| row.get(0))
+            .optional()?;
+        let version: u32 = match version {
+            Some(version) => version,
+            None => {
+                tx.execute("INSERT INTO schema_version (version) VALUES (0)", [])?;
+                0
+            }
+        };
+        if version > SCHEMA_VERSION {
+            return Err(Error::SchemaVersionError(version));
+        }
+        for migration in &MIGRATIONS[version as usize..] {
+            tx.execute_batch(migration)?;
+        }
+        tx.execute(
+            "UPDATE schema_version SET version = ?",
+            params![SCHEMA_VERSION],
+        )?;
+        tx.commit()?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Schema version of the database
+    pub fn schema_version(&self) -> Result<u32, Error> {
+        Ok(self
+            .connection()?
+            .query_row("SELECT version FROM schema_version", [], |row| row.get(0))?)
+    }
+
+    fn connection(&self) -> Result<MutexGuard<'_, Connection>, Error> {
+        self.connection.lock().map_err(|_| Error::MutexPoisoned)
+    }
+
+    /// Key of the identifier in the other tables, registered if it's new
+    ///
+    /// Only for writes, lookups use `stored_key`.
+    fn designated_key(connection: &Connection, id: &IdentifierPrefix) -> Result<i64, Error> {
+        let prefix = id.to_str();
+        connection.execute(
+            "INSERT OR IGNORE INTO iids (prefix) VALUES (?)",
+            params![prefix],
+        )?;
+        Ok(connection.query_row(
+            "SELECT id FROM iids WHERE prefix = ?",
+            params![prefix],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Key of the identifier in the other tables, `None` if nothing was
+    /// ever stored for it
+    fn stored_key(connection: &Connection, id: &IdentifierPrefix) -> Result<Option<i64>, Error> {
+        Ok(connection
+            .query_row(
+                "SELECT id FROM iids WHERE prefix = ?",
+                params![id.to_str()],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Value of the identifier in a table with a row per identifier
+    fn get<T: DeserializeOwned>(
+        connection: &Connection,
+        table: &str,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<T>, Error> {
+        let key = match Self::stored_key(connection, id)? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let value: Option<Vec<u8>> = connection
+            .query_row(
+                &format!("SELECT value FROM {} WHERE identifier = ?", table),
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value
+            .map(|value| serde_cbor::from_slice(&value))
+            .transpose()?)
+    }
+
+    fn put<T: Serialize>(
+        connection: &Connection,
+        table: &str,
+        id: &IdentifierPrefix,
+        value: &T,
+    ) -> Result<(), Error> {
+        let key = Self::designated_key(connection, id)?;
+        connection.execute(
+            &format!(
+                "INSERT OR REPLACE INTO {} (identifier, value) VALUES (?, ?)",
+                table
+            ),
+            params![key, serde_cbor::to_vec(value)?],
+        )?;
+        Ok(())
+    }
+
+    /// Deletes all rows of the identifier
+    fn delete(connection: &Connection, table: &str, id: &IdentifierPrefix) -> Result<(), Error> {
+        if let Some(key) = Self::stored_key(connection, id)? {
+            connection.execute(
+                &format!("DELETE FROM {} WHERE identifier = ?", table),
+                params![key],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Row ids and values of the identifier in the order they were stored,
+    /// only those about `event` if given
+    fn rows<T: DeserializeOwned>(
+        connection: &Connection,
+        table: &str,
+        key: i64,
+        event: Option<(u64, String)>,
+    ) -> Result<Vec<(i64, T)>, Error> {
+        let (sn, digest) = event.unzip();
+        let mut statement = connection.prepare(&format!(
+            "SELECT rowid, value FROM {} WHERE identifier = ?1
+                AND (?2 IS NULL OR (sn = ?2 AND digest = ?3)) ORDER BY rowid",
+            table
+        ))?;
+        let rows = statement.query_map(params![key, sn, digest], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })?;
+        rows.map(|row| {
+            let (rowid, value) = row?;
+            Ok((rowid, serde_cbor::from_slice(&value)?))
+        })
+        .collect()
+    }
+
+    /// Values of the identifier, `None` if it never had any
+    fn iter_values<T: DeserializeOwned>(
+        &self,
+        table: &str,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<T>>, Error> {
+        let connection = self.connection()?;
+        let key = match Self::stored_key(&connection, id)? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let values: Vec<T> = Self::rows(&connection, table, key, None)?
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect();
+        if values.is_empty() && !Self::written(&connection, table, key)? {
+            return Ok(None);
+        }
+        Ok(Some(values.into_iter()))
+    }
+
+    /// Events of the KEL of the identifier
+    fn kel(
+        connection: &Connection,
+        id: &IdentifierPrefix,
+    ) -> Result<Vec<SignedEventMessage>, Error> {
+        Ok(match Self::stored_key(connection, id)? {
+            Some(key) => {
+                Self::rows::<TimestampedSignedEventMessage>(connection, "kels", key, None)?
+                    .into_iter()
+                    .map(|(_, event)| event.signed_event_message)
+                    .collect()
+            }
+            None => vec![],
+        })
+    }
+
+    fn push<T: EventValue>(
+        connection: &Connection,
+        table: &str,
+        id: &IdentifierPrefix,
+        value: T,
+    ) -> Result<(), Error> {
+        let key = Self::designated_key(connection, id)?;
+        let (sn, digest) = value.event();
+        connection.execute(
+            &format!(
+                "INSERT INTO {} (identifier, sn, digest, value) VALUES (?, ?, ?, ?)",
+                table
+            ),
+            params![key, sn, digest, serde_cbor::to_vec(&value)?],
+        )?;
+        connection.execute(
+            "INSERT OR IGNORE INTO written (identifier, name) VALUES (?, ?)",
+            params![key, table],
+        )?;
+        Ok(())
+    }
+
+    /// Whether the identifier ever had values in `table`
+    fn written(connection: &Connection, table: &str, key: i64) -> Result<bool, Error> {
+        Ok(connection
+            .query_row(
+                "SELECT 1 FROM written WHERE identifier = ? AND name = ?",
+                params![key, table],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    /// Pushes `value` unless it's already present
+    fn push_unique<T: EventValue>(
+        connection: &Connection,
+        table: &str,
+        id: &IdentifierPrefix,
+        value: T,
+    ) -> Result<(), Error> {
+        if let Some(key) = Self::stored_key(connection, id)? {
+            let present = Self::rows::<T>(connection, table, key, Some(value.event()))?
+                .into_iter()
+                .any(|(_, v)| v == value);
+            if present {
+                return Ok(());
+            }
+        }
+        Self::push(connection, table, id, value)
+    }
+
+    /// Pushes `value` in place of the values about the same event
+    fn replace<T: EventValue>(
+        connection: &Connection,
+        table: &str,
+        id: &IdentifierPrefix,
+        value: T,
+    ) -> Result<(), Error> {
+        if let Some(key) = Self::stored_key(connection, id)? {
+            let (sn, digest) = value.event();
+            connection.execute(
+                &format!(
+                    "DELETE FROM {} WHERE identifier = ? AND sn = ? AND digest = ?",
+                    table
+                ),
+                params![key, sn, digest],
+            )?;
+        }
+        Self::push(connection, table, id, value)
+    }
+
+    /// Removes all values equal to `value`
+    fn remove<T: EventValue>(
+        connection: &Connection,
+        table: &str,
+        id: &IdentifierPrefix,
+        value: &T,
+    ) -> Result<(), Error> {
+        let key = match Self::stored_key(connection, id)? {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        for (rowid, v) in Self::rows::<T>(connection, table, key, Some(value.event()))? {
+            if &v == value {
+                connection.execute(
+                    &format!("DELETE FROM {} WHERE rowid = ?", table),
+                    params![rowid],
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EventStorage for SqliteEventDatabase {
+    fn add_kel_finalized_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Self::push(
+            &*self.connection()?,
+            "kels",
+            id,
+            TimestampedSignedEventMessage::from(event),
+        )?;
+        self.remove_key_state(id)
+    }
+
+    fn get_kel_finalized_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.iter_values("kels", id)
+    }
+
+    fn remove_kel_finalized_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        Self::remove(
+            &*self.connection()?,
+            "kels",
+            id,
+            &TimestampedSignedEventMessage::from(event),
+        )?;
+        self.remove_key_state(id)
+    }
+
+    /// Accept Event
+    ///
+    /// Applies `acceptance` in a single SQL transaction, which also reads
+    /// the KEL to check the acceptance against it. The transaction takes the
+    /// write lock from the start, so connections to the same file from
+    /// other databases wait for it.
+    fn accept_event(&self, acceptance: EventAcceptance) -> Result<(), Error> {
+        let id = &acceptance.event.event_message.event.get_prefix();
+        let mut connection = self.connection()?;
+        let tx = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if !acceptance.extends(&Self::kel(&tx, id)?) {
+            return Err(Error::KelChangedError);
+        }
+        for event in &acceptance.superseded {
+            Self::remove(&tx, "kels", id, &TimestampedSignedEventMessage::from(event))?;
+        }
+        Self::push(
+            &tx,
+            "kels",
+            id,
+            TimestampedSignedEventMessage::from(acceptance.event),
+        )?;
+        for event in acceptance.duplicitous {
+            Self::push(&tx, "dels", id, TimestampedSignedEventMessage::from(event))?;
+        }
+        for rct in &acceptance.escrowed_receipts_nt {
+            Self::remove(&tx, "ures", id, rct)?;
+        }
+        for rct in acceptance.receipts_nt {
+            Self::push(&tx, "rcts", id, rct)?;
+        }
+        for vrc in &acceptance.escrowed_receipts_t {
+            Self::remove(&tx, "vres", id, vrc)?;
+        }
+        for vrc in acceptance.receipts_t {
+            Self::push(&tx, "vrcs", id, vrc)?;
+        }
+        Self::put(&tx, "ksts", id, &acceptance.state)?;
+        Ok(tx.commit()?)
+    }
+
+    fn get_key_state(&self, id: &IdentifierPrefix) -> Result<Option<IdentifierState>, Error> {
+        Self::get(&*self.connection()?, "ksts", id)
+    }
+
+    fn update_key_state(
+        &self,
+        id: &IdentifierPrefix,
+        state: &IdentifierState,
+    ) -> Result<(), Error> {
+        let mut connection = self.connection()?;
+        let tx = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if is_head_state(state, &Self::kel(&tx, id)?) {
+            Self::put(&tx, "ksts", id, state)?;
+        }
+        Ok(tx.commit()?)
+    }
+
+    fn remove_key_state(&self, id: &IdentifierPrefix) -> Result<(), Error> {
+        Self::delete(&*self.connection()?, "ksts", id)
+    }
+
+    fn add_receipt_t(
+        &self,
+        receipt: SignedTransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Self::push(&*self.connection()?, "vrcs", id, receipt)
+    }
+
+    fn get_receipts_t(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedTransferableReceipt>>, Error> {
+        self.iter_values("vrcs", id)
+    }
+
+    fn add_receipt_nt(
+        &self,
+        receipt: SignedNontransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Self::push(&*self.connection()?, "rcts", id, receipt)
+    }
+
+    fn get_receipts_nt(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedNontransferableReceipt>>, Error> {
+        self.iter_values("rcts", id)
+    }
+
+    fn remove_receipts_nt(&self, id: &IdentifierPrefix) -> Result<(), Error> {
+        Self::delete(&*self.connection()?, "rcts", id)
+    }
+
+    fn add_escrow_t_receipt(
+        &self,
+        receipt: SignedTransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Self::push(&*self.connection()?, "vres", id, receipt)
+    }
+
+    fn get_escrow_t_receipts(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedTransferableReceipt>>, Error> {
+        self.iter_values("vres", id)
+    }
+
+    fn remove_escrow_t_receipt(
+        &self,
+        id: &IdentifierPrefix,
+        receipt: &SignedTransferableReceipt,
+    ) -> Result<(), Error> {
+        Self::remove(&*self.connection()?, "vres", id, receipt)
+    }
+
+    fn add_escrow_nt_receipt(
+        &self,
+        receipt: SignedNontransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Self::push(&*self.connection()?, "ures", id, receipt)
+    }
+
+    fn get_escrow_nt_receipts(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedNontransferableReceipt>>, Error> {
+        self.iter_values("ures", id)
+    }
+
+    fn remove_escrow_nt_receipt(
+        &self,
+        id: &IdentifierPrefix,
+        receipt: &SignedNontransferableReceipt,
+    ) -> Result<(), Error> {
+        Self::remove(&*self.connection()?, "ures", id, receipt)
+    }
+
+    fn add_likely_duplicious_event(
+        &self,
+        event: EventMessage<KeyEvent>,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Self::push(
+            &*self.connection()?,
+            "ldes",
+            id,
+            TimestampedEventMessage::new(event),
+        )
+    }
+
+    fn get_likely_duplicitous_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedEventMessage>>, Error> {
+        self.iter_values("ldes", id)
+    }
+
+    fn add_duplicious_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Self::push(
+            &*self.connection()?,
+            "dels",
+            id,
+            TimestampedSignedEventMessage::from(event),
+        )
+    }
+
+    fn get_duplicious_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.iter_values("dels", id)
+    }
+
+    fn add_out_of_order_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Self::push_unique(
+            &*self.connection()?,
+            "ooes",
+            id,
+            TimestampedSignedEventMessage::from(event),
+        )
+    }
+
+    fn get_out_of_order_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.iter_values("ooes", id)
+    }
+
+    fn remove_out_of_order_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        Self::remove(
+            &*self.connection()?,
+            "ooes",
+            id,
+            &TimestampedSignedEventMessage::from(event),
+        )
+    }
+
+    fn add_partially_signed_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        // merged signatures replace the ones escrowed for the same event
+        Self::replace(
+            &*self.connection()?,
+            "pses",
+            id,
+            TimestampedSignedEventMessage::from(event),
+        )
+    }
+
+    fn get_partially_signed_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.iter_values("pses", id)
+    }
+
+    fn remove_partially_signed_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        Self::remove(
+            &*self.connection()?,
+            "pses",
+            id,
+            &TimestampedSignedEventMessage::from(event),
+        )
+    }
+
+    fn add_partially_witnessed_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Self::push_unique(
+            &*self.connection()?,
+            "pwes",
+            id,
+            TimestampedSignedEventMessage::from(event),
+        )
+    }
+
+    fn get_partially_witnessed_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        self.iter_values("pwes", id)
+    }
+
+    fn remove_partially_witnessed_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        Self::remove(
+            &*self.connection()?,
+            "pwes",
+            id,
+            &TimestampedSignedEventMessage::from(event),
+        )
+    }
+
+    #[cfg(feature = "query")]
+    fn update_accepted_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error> {
+        let mut connection = self.connection()?;
+        let tx = connection.transaction()?;
+        if let Some(key) = Self::stored_key(&tx, id)? {
+            let route = rpy.reply.event.get_route();
+            for (rowid, accepted) in Self::rows::<SignedReply>(&tx, "knas", key, None)? {
+                if accepted.reply.event.get_route() == route {
+                    tx.execute("DELETE FROM knas WHERE rowid = ?", params![rowid])?;
+                }
+            }
+        }
+        Self::push(&tx, "knas", id, rpy)?;
+        Ok(tx.commit()?)
+    }
+
+    #[cfg(feature = "query")]
+    fn get_accepted_replys(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedReply>>, Error> {
+        self.iter_values("knas", id)
+    }
+
+    #[cfg(feature = "query")]
+    fn remove_accepted_reply(&self, id: &IdentifierPrefix, rpy: SignedReply) -> Result<(), Error> {
+        Self::remove(&*self.connection()?, "knas", id, &rpy)
+    }
+
+    #[cfg(feature = "query")]
+    fn add_escrowed_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error> {
+        Self::push(&*self.connection()?, "knes", id, rpy)
+    }
+
+    #[cfg(feature = "query")]
+    fn get_escrowed_replys(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedReply>>, Error> {
+        self.iter_values("knes", id)
+    }
+
+    #[cfg(feature = "query")]
+    fn remove_escrowed_reply(&self, id: &IdentifierPrefix, rpy: SignedReply) -> Result<(), Error> {
+        Self::remove(&*self.connection()?, "knes", id, &rpy)
+    }
+
+    #[cfg(feature = "query")]
+    fn get_all_escrowed_replys(&self) -> Result<Option<DbIter<SignedReply>>, Error> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare("SELECT value FROM knes ORDER BY rowid")?;
+        let mut rpys = vec![];
+        for value in statement.query_map([], |row| row.get::<_, Vec<u8>>(0))? {
+            rpys.push(serde_cbor::from_slice(&value?)?);
+        }
+        Ok(Some(rpys.into_iter()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_version() -> Result<(), Error> {
+        use tempfile::Builder;
+
+        let root = Builder::new().prefix("test-db").tempdir().unwrap();
+        let path = root.path().join("events.db");
+
+        let db = SqliteEventDatabase::new(path.as_path())?;
+        assert_eq!(db.schema_version()?, SCHEMA_VERSION);
+        drop(db);
+
+        // Reopening an up to date database leaves it as is.
+        let db = SqliteEventDatabase::new(path.as_path())?;
+        assert_eq!(db.schema_version()?, SCHEMA_VERSION);
+        drop(db);
+
+        // Databases of a newer schema are refused.
+        Connection::open(&path)?.execute(
+            "UPDATE schema_version SET version = ?",
+            params![SCHEMA_VERSION + 1],
+        )?;
+        assert!(matches!(
+            SqliteEventDatabase::new(path.as_path()),
+            Err(Error::SchemaVersionError(version)) if version == SCHEMA_VERSION + 1
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rows() -> Result<(), Error> {
+        use crate::{
+            event_message::signed_event_message::Message, event_parsing::message::signed_message,
+        };
+        use std::convert::TryFrom;
+        use tempfile::Builder;
+
+        let root = Builder::new().prefix("test-db").tempdir().unwrap();
+        let path = root.path().join("events.db");
+        let db = SqliteEventDatabase::new(path.as_path())?;
+
+        let icp_raw = br#"{"v":"KERI10JSON000120_","t":"icp","d":"EsZuhYAPBDnexP3SOl9YsGvWBrYkjYcRjomUYmCcLAYY","i":"EsZuhYAPBDnexP3SOl9YsGvWBrYkjYcRjomUYmCcLAYY","s":"0","kt":"1","k":["DSuhyBcPZEZLK-fcw5tzHn2N46wRCG_ZOoeKtWTOunRA"],"n":"EPYuj8mq_PYYsoBKkzX1kxSPGYBWaIya3slgCOyOtlqU","bt":"0","b":[],"c":[],"a":[]}-AABAAWKO9bl3OhABTaevxYiXQ1poRIGfM9ndMPq4bvrKmU_3pTN3VLNDYOI8pJBeAQxRtajQn4CSWOqgdGnmeG6fBCQ"#;
+        let icp = match Message::try_from(signed_message(icp_raw).unwrap().1)? {
+            Message::Event(icp) => *icp,
+            _ => unreachable!(),
+        };
+        let id = icp.event_message.event.get_prefix();
+
+        // Reading doesn't register the identifier.
+        assert!(db.get_kel_finalized_events(&id)?.is_none());
+        assert!(db.get_key_state(&id)?.is_none());
+        let identifiers: u32 =
+            db.connection()?
+                .query_row("SELECT COUNT(*) FROM iids", [], |row| row.get(0))?;
+        assert_eq!(identifiers, 0);
+
+        // Each event is a row of its own, found by its sn and digest.
+        db.add_kel_finalized_event(icp.clone(), &id)?;
+        let (sn, digest): (u64, String) =
+            db.connection()?
+                .query_row("SELECT sn, digest FROM kels", [], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?;
+        assert_eq!(sn, 0);
+        assert_eq!(digest, icp.event_message.get_digest().to_str());
+        assert_eq!(db.get_kel_finalized_events(&id)?.unwrap().count(), 1);
+
+        db.remove_kel_finalized_event(&id, &icp)?;
+        assert_eq!(db.get_kel_finalized_events(&id)?.unwrap().count(), 0);
+
+        Ok(())
+    }
+}
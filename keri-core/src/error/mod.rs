@@ -49,9 +49,18 @@ pub enum Error {
     #[error("Error while applying event: duplicate event")]
     EventDuplicateError,
 
+    #[error("Error while applying event: duplicitous event")]
+    DuplicitousEventError,
+
+    #[error("Error while applying event: KEL changed while the event was processed")]
+    KelChangedError,
+
     #[error("Not enough signatures while verifying")]
     NotEnoughSigsError,
 
+    #[error("Not enough witness receipts")]
+    NotEnoughReceiptsError,
+
     #[error("Signature verification failed")]
     SignatureVerificationError,
 
@@ -92,6 +101,17 @@ pub enum Error {
     #[error(transparent)]
     SledError(#[from] sled::Error),
 
+    #[cfg(feature = "sqlite-db")]
+    #[error(transparent)]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[cfg(feature = "sqlite-db")]
+    #[error("Unsupported database schema version {0}")]
+    SchemaVersionError(u32),
+
+    #[error("Storage backend error: {0}")]
+    BackendError(String),
+
     #[error(transparent)]
     SerdeSerError(#[from] serializer_error::Error),
 
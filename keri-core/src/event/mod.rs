@@ -73,7 +73,7 @@ impl EventSemantics for Event {
                 if self.prefix != state.prefix {
                     return Err(Error::SemanticError("Prefix does not match".to_string()));
                 // sn must be incremented
-                // TODO recovery will break this rule when we implement it
+                // the processor handles lower sn as duplicity or recovery
                 } else if self.sn < state.sn + 1 {
                     return Err(Error::EventDuplicateError);
                 } else if self.sn > state.sn + 1 {
@@ -131,6 +131,13 @@ impl EventMsgBuilder {
         }
     }
 
+    pub fn with_witness_threshold(self, witness_threshold: u64) -> Self {
+        EventMsgBuilder {
+            witness_threshold,
+            ..self
+        }
+    }
+
     pub fn with_witness_to_add(self, witness_to_add: &[BasicPrefix]) -> Self {
         EventMsgBuilder {
             witness_to_add: witness_to_add.to_vec(),
@@ -170,7 +177,7 @@ impl EventMsgBuilder {
                         initial_witnesses: self.witnesses,
                     },
                     inception_configuration: vec![],
-                    data: vec![],
+                    data: self.data,
                 };
 
                 match prefix {
@@ -210,9 +217,12 @@ impl EventMsgBuilder {
             EventTypeTag::Dip => {
                 let icp_data = InceptionEvent {
                     key_config,
-                    witness_config: InceptionWitnessConfig::default(),
+                    witness_config: InceptionWitnessConfig {
+                        tally: self.witness_threshold,
+                        initial_witnesses: self.witnesses,
+                    },
                     inception_configuration: vec![],
-                    data: vec![],
+                    data: self.data,
                 };
                 DelegatedInceptionEvent {
                     inception_data: icp_data,
@@ -224,7 +234,11 @@ impl EventMsgBuilder {
                 let rotation_data = RotationEvent {
                     previous_event_hash: self.prev_event,
                     key_config,
-                    witness_config: WitnessConfig::default(),
+                    witness_config: WitnessConfig {
+                        tally: self.witness_threshold,
+                        prune: self.witness_to_remove,
+                        graft: self.witness_to_add,
+                    },
                     data: self.data,
                 };
                 Event::new(prefix, self.sn, EventData::Drt(rotation_data))
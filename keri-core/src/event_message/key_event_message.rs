@@ -60,26 +60,45 @@ impl EventMessage<KeyEvent> {
         }
     }
 
+    /// Verify SAID
+    ///
+    /// Checks that the event digest is bound to the event content.
+    pub fn verify_said(&self) -> Result<bool, Error> {
+        Ok(self
+            .event
+            .get_digest()
+            .verify_binding(&self.to_derivation_data()?))
+    }
+
     fn to_derivation_data(&self) -> Result<Vec<u8>, Error> {
         Ok(match self.event.get_event_data() {
-            EventData::Icp(icp) => DummyInceptionEvent::dummy_inception_data(
-                icp,
-                &self.event.get_digest().derivation,
-                self.serialization_info.kind,
-            )?
-            .serialize()?,
-            EventData::Dip(dip) => DummyInceptionEvent::dummy_delegated_inception_data(
-                dip,
-                &self.event.get_digest().derivation,
-                self.serialization_info.kind,
-            )?
-            .serialize()?,
+            // Only a self-addressing prefix is derived from the event itself
+            EventData::Icp(icp) if self.is_self_addressing() => {
+                DummyInceptionEvent::dummy_inception_data(
+                    icp,
+                    &self.event.get_digest().derivation,
+                    self.serialization_info.kind,
+                )?
+                .serialize()?
+            }
+            EventData::Dip(dip) if self.is_self_addressing() => {
+                DummyInceptionEvent::dummy_delegated_inception_data(
+                    dip,
+                    &self.event.get_digest().derivation,
+                    self.serialization_info.kind,
+                )?
+                .serialize()?
+            }
             _ => {
                 let dummy_event: DummyEventMessage<_> = self.clone().into();
                 dummy_event.serialize()?
             }
         })
     }
+
+    fn is_self_addressing(&self) -> bool {
+        matches!(self.event.get_prefix(), IdentifierPrefix::SelfAddressing(_))
+    }
 }
 
 impl EventSemantics for EventMessage<KeyEvent> {
@@ -132,7 +132,7 @@ impl<D: Digestible + Typeable + Serialize + Clone> Serialize for EventMessage<D>
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct TimestampedEventMessage {
     pub timestamp: DateTime<Local>,
     pub event_message: EventMessage<KeyEvent>,
@@ -433,4 +433,36 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_verify_said() -> Result<(), Error> {
+        use crate::event_message::event_msg_builder::EventMsgBuilder;
+
+        for event_type in [EventTypeTag::Icp, EventTypeTag::Rot, EventTypeTag::Ixn] {
+            let event = EventMsgBuilder::new(event_type).build()?;
+            assert!(event.verify_said()?);
+
+            let mut tampered = event.clone();
+            tampered.event.content.sn += 1;
+            assert!(!tampered.verify_said()?);
+        }
+
+        // Self-addressing identifier
+        let keys = (0..2)
+            .map(|_| {
+                let kp = Keypair::generate(&mut OsRng);
+                Basic::Ed25519.derive(PublicKey::new(kp.public.to_bytes().to_vec()))
+            })
+            .collect();
+        let icp = EventMsgBuilder::new(EventTypeTag::Icp)
+            .with_keys(keys)
+            .build()?;
+        assert!(matches!(
+            icp.event.get_prefix(),
+            IdentifierPrefix::SelfAddressing(_)
+        ));
+        assert!(icp.verify_said()?);
+
+        Ok(())
+    }
 }
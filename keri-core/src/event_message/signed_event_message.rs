@@ -69,7 +69,7 @@ impl PartialEq for SignedEventMessage {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TimestampedSignedEventMessage {
     pub timestamp: DateTime<Local>,
     pub signed_event_message: SignedEventMessage,
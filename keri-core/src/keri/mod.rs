@@ -4,7 +4,7 @@ use std::{
 };
 
 use crate::{
-    database::sled::SledEventDatabase,
+    database::{sled::SledEventDatabase, EventStorage},
     derivation::basic::Basic,
     derivation::self_addressing::SelfAddressing,
     derivation::self_signing::SelfSigning,
@@ -38,21 +38,19 @@ use universal_wallet::prelude::{Content, UnlockedWallet};
 mod test;
 #[cfg(feature = "query")]
 pub mod witness;
-pub struct Keri<K: KeyManager + 'static> {
+pub struct Keri<K: KeyManager + 'static, D: EventStorage = SledEventDatabase> {
     prefix: IdentifierPrefix,
     key_manager: Arc<Mutex<K>>,
-    processor: EventProcessor,
+    processor: EventProcessor<D>,
 }
 
 #[cfg(feature = "wallet")]
-impl Keri<UnlockedWallet> {
+impl<D: EventStorage> Keri<UnlockedWallet, D> {
     /// Instantiates KERI with freshly created and pre-populated wallet
     /// Wallet has ECDSA and X25519 key pairs
     /// Only available with crate `wallet` feature.
     ///
-    pub fn new_with_fresh_wallet(
-        db: Arc<SledEventDatabase>,
-    ) -> Result<Keri<UnlockedWallet>, Error> {
+    pub fn new_with_fresh_wallet(db: Arc<D>) -> Result<Keri<UnlockedWallet, D>, Error> {
         use crate::{
             prefix::Prefix,
             signer::wallet::{incept_keys, CURRENT},
@@ -79,9 +77,9 @@ impl Keri<UnlockedWallet> {
     }
 }
 
-impl<K: KeyManager> Keri<K> {
+impl<K: KeyManager, D: EventStorage> Keri<K, D> {
     // incept a state and keys
-    pub fn new(db: Arc<SledEventDatabase>, key_manager: Arc<Mutex<K>>) -> Result<Keri<K>, Error> {
+    pub fn new(db: Arc<D>, key_manager: Arc<Mutex<K>>) -> Result<Keri<K, D>, Error> {
         Ok(Keri {
             prefix: IdentifierPrefix::default(),
             key_manager,
@@ -89,6 +87,21 @@ impl<K: KeyManager> Keri<K> {
         })
     }
 
+    /// Instantiates KERI for an already incepted identifier
+    ///
+    /// Events of `prefix` are expected to be present in `db`.
+    pub fn open(
+        db: Arc<D>,
+        key_manager: Arc<Mutex<K>>,
+        prefix: IdentifierPrefix,
+    ) -> Result<Keri<K, D>, Error> {
+        Ok(Keri {
+            prefix,
+            key_manager,
+            processor: EventProcessor::new(db),
+        })
+    }
+
     /// Getter of the instance prefix
     ///
     pub fn prefix(&self) -> &IdentifierPrefix {
@@ -103,7 +116,7 @@ impl<K: KeyManager> Keri<K> {
 
     // Getter of the DB instance behind own processor
     ///
-    pub fn db(&self) -> Arc<SledEventDatabase> {
+    pub fn db(&self) -> Arc<D> {
         Arc::clone(&self.processor.db)
     }
 
@@ -190,7 +203,7 @@ impl<K: KeyManager> Keri<K> {
     /// Seal gets added to our KEL db and returned back as `SignedEventMessage`
     ///
     pub fn interact(&self, peer: IdentifierPrefix) -> Result<SignedEventMessage, Error> {
-        let next_sn = match self.processor.db.get_kel_finalized_events(&self.prefix) {
+        let next_sn = match self.processor.db.get_kel_finalized_events(&self.prefix)? {
             Some(mut events) => match events.next_back() {
                 Some(db_event) => db_event.signed_event_message.event_message.event.get_sn() + 1,
                 None => return Err(Error::InvalidIdentifierStat),
@@ -281,6 +294,13 @@ impl<K: KeyManager> Keri<K> {
             }
             None => vec![],
         };
+        self.anchor(seal_list)
+    }
+
+    /// Anchor seals in own KEL
+    ///
+    /// Makes, signs and processes an interaction event carrying `seal_list`.
+    pub fn anchor(&self, seal_list: Vec<Seal>) -> Result<SignedEventMessage, Error> {
         let state = self
             .processor
             .compute_state(&self.prefix)?
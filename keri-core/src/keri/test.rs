@@ -169,6 +169,63 @@ fn test_direct_mode() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn test_verify_with_historical_keys() -> Result<(), Error> {
+    use crate::{
+        derivation::self_signing::SelfSigning, event_message::signature::Signature,
+        prefix::AttachedSignaturePrefix, signer::CryptoBox, signer::KeyManager,
+    };
+    use tempfile::Builder;
+
+    let root = Builder::new().prefix("test-db").tempdir().unwrap();
+    let db = Arc::new(SledEventDatabase::new(root.path()).unwrap());
+    let key_manager = Arc::new(Mutex::new(CryptoBox::new()?));
+    let mut alice = Keri::new(Arc::clone(&db), Arc::clone(&key_manager))?;
+    alice.incept(None)?;
+
+    // Sign some data with keys established by inception event.
+    let data = b"refs/heads/master";
+    let sign = |km: &Arc<Mutex<CryptoBox>>| -> Result<Vec<AttachedSignaturePrefix>, Error> {
+        Ok(vec![AttachedSignaturePrefix::new(
+            SelfSigning::Ed25519Sha512,
+            km.lock().unwrap().sign(data)?,
+            0,
+        )])
+    };
+    let icp_seal = alice
+        .processor
+        .get_last_establishment_event_seal(alice.prefix())?
+        .unwrap();
+    let icp_signatures = sign(&key_manager)?;
+
+    alice.rotate()?;
+    let rot_seal = alice
+        .processor
+        .get_last_establishment_event_seal(alice.prefix())?
+        .unwrap();
+    assert_eq!(rot_seal.sn, 1);
+
+    // Signature is still valid against the key state it was made with.
+    let signature = Signature::Transferable(icp_seal.clone(), icp_signatures.clone());
+    assert!(alice.processor.verify(data, &signature).is_ok());
+
+    // But not against the current one.
+    let signature = Signature::Transferable(rot_seal.clone(), icp_signatures);
+    assert!(matches!(
+        alice.processor.verify(data, &signature),
+        Err(Error::SignatureVerificationError)
+    ));
+
+    // New keys verify against the rotation event only.
+    let rot_signatures = sign(&key_manager)?;
+    let signature = Signature::Transferable(rot_seal, rot_signatures.clone());
+    assert!(alice.processor.verify(data, &signature).is_ok());
+    let signature = Signature::Transferable(icp_seal, rot_signatures);
+    assert!(alice.processor.verify(data, &signature).is_err());
+
+    Ok(())
+}
+
 #[cfg(feature = "query")]
 #[test]
 fn test_qry_rpy() -> Result<(), Error> {
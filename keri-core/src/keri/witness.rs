@@ -9,7 +9,7 @@ use crate::query::{
 };
 
 use crate::{
-    database::sled::SledEventDatabase,
+    database::{sled::SledEventDatabase, EventStorage},
     derivation::{basic::Basic, self_addressing::SelfAddressing, self_signing::SelfSigning},
     error::Error,
     event::SerializationFormats,
@@ -18,19 +18,25 @@ use crate::{
     signer::{CryptoBox, KeyManager},
 };
 
-pub struct Witness {
+pub struct Witness<D: EventStorage = SledEventDatabase> {
     pub prefix: BasicPrefix,
     signer: CryptoBox,
-    pub processor: EventProcessor,
+    pub processor: EventProcessor<D>,
 }
 
 impl Witness {
     pub fn new(path: &Path) -> Result<Self, Error> {
+        let witness_db = Arc::new(SledEventDatabase::new(path).unwrap());
+        Self::new_with_db(witness_db)
+    }
+}
+
+impl<D: EventStorage> Witness<D> {
+    /// Instantiates a witness keeping its events in `db`
+    ///
+    pub fn new_with_db(db: Arc<D>) -> Result<Self, Error> {
         let signer = CryptoBox::new()?;
-        let processor = {
-            let witness_db = Arc::new(SledEventDatabase::new(path).unwrap());
-            EventProcessor::new(witness_db.clone())
-        };
+        let processor = EventProcessor::new_witness(db);
         let prefix = Basic::Ed25519.derive(signer.public_key()?);
         Ok(Self {
             prefix,
@@ -0,0 +1,61 @@
+use std::{
+    collections::HashMap,
+    sync::{Condvar, Mutex},
+    thread::{self, ThreadId},
+};
+
+use crate::{
+    error::Error,
+    prefix::{IdentifierPrefix, Prefix},
+};
+
+/// Identifier Locks
+///
+/// Serializes the processing of each identifier, while different
+/// identifiers are processed in parallel. The thread holding the lock
+/// of an identifier may lock it again, as processing an event goes on
+/// to the escrowed events of the same identifier.
+#[derive(Default)]
+pub(crate) struct IdentifierLocks {
+    // thread holding the lock of an identifier and how many times
+    held: Mutex<HashMap<String, (ThreadId, usize)>>,
+    released: Condvar,
+}
+
+impl IdentifierLocks {
+    /// Blocks until no other thread holds the lock of the identifier
+    pub fn lock(&self, id: &IdentifierPrefix) -> Result<IdentifierGuard<'_>, Error> {
+        let key = id.to_str();
+        let current = thread::current().id();
+        let mut held = self.held.lock().map_err(|_| Error::MutexPoisoned)?;
+        while matches!(held.get(&key), Some((owner, _)) if *owner != current) {
+            held = self.released.wait(held).map_err(|_| Error::MutexPoisoned)?;
+        }
+        held.entry(key.clone()).or_insert((current, 0)).1 += 1;
+        Ok(IdentifierGuard { locks: self, key })
+    }
+}
+
+/// Releases the lock of the identifier when dropped
+pub(crate) struct IdentifierGuard<'a> {
+    locks: &'a IdentifierLocks,
+    key: String,
+}
+
+impl Drop for IdentifierGuard<'_> {
+    fn drop(&mut self) {
+        // the map is never left half updated, so poisoning can be ignored
+        let mut held = self
+            .locks
+            .held
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((_, count)) = held.get_mut(&self.key) {
+            *count -= 1;
+            if *count == 0 {
+                held.remove(&self.key);
+                self.locks.released.notify_all();
+            }
+        }
+    }
+}
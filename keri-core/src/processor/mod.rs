@@ -4,11 +4,13 @@ use crate::query::{key_state_notice::KeyStateNotice, reply::SignedReply, QueryEr
 use chrono::{DateTime, FixedOffset};
 use std::sync::Arc;
 
+use self::locks::IdentifierLocks;
 use crate::{
-    database::sled::SledEventDatabase,
+    database::{sled::SledEventDatabase, EventAcceptance, EventStorage},
     error::Error,
     event::{
         event_data::EventData,
+        receipt::Receipt,
         sections::{
             seal::{EventSeal, Seal},
             KeyConfig,
@@ -23,32 +25,77 @@ use crate::{
             TimestampedSignedEventMessage,
         },
     },
-    prefix::{IdentifierPrefix, SelfAddressingPrefix},
+    prefix::{BasicPrefix, IdentifierPrefix, SelfAddressingPrefix},
     state::{EventSemantics, IdentifierState},
 };
 
 #[cfg(feature = "async")]
 pub mod async_processing;
+mod locks;
 #[cfg(test)]
 mod tests;
 
-pub struct EventProcessor {
-    pub db: Arc<SledEventDatabase>,
+/// Times an event is validated while the KEL keeps changing under it,
+/// before `Error::KelChangedError` is returned
+const KEL_CHANGED_ATTEMPTS: usize = 16;
+
+pub struct EventProcessor<D: EventStorage = SledEventDatabase> {
+    pub db: Arc<D>,
+    // whether events wait for receipts of their witnesses to be accepted
+    witnessed: bool,
+    // held while the KEL or receipts of an identifier are processed
+    locks: IdentifierLocks,
 }
 
-impl EventProcessor {
-    pub fn new(db: Arc<SledEventDatabase>) -> Self {
-        Self { db }
+impl<D: EventStorage> EventProcessor<D> {
+    pub fn new(db: Arc<D>) -> Self {
+        Self {
+            db,
+            witnessed: true,
+            locks: IdentifierLocks::default(),
+        }
+    }
+
+    /// Event processor of a witness
+    ///
+    /// A witness has to know the events it's asked to receipt before the
+    /// other witnesses receipt them, so events are accepted regardless of
+    /// their witness threshold.
+    pub fn new_witness(db: Arc<D>) -> Self {
+        Self {
+            db,
+            witnessed: false,
+            locks: IdentifierLocks::default(),
+        }
     }
 
     /// Compute State for Prefix
     ///
     /// Returns the current State associated with
-    /// the given Prefix
+    /// the given Prefix, cached until its KEL changes
     pub fn compute_state(&self, id: &IdentifierPrefix) -> Result<Option<IdentifierState>, Error> {
+        if let Some(state) = self.db.get_key_state(id)? {
+            return Ok(Some(state));
+        }
+        // The storage drops the replayed state if the KEL changed meanwhile,
+        // so it's cached without locking the identifier. Not waiting for the
+        // lock keeps lookups of other KELs during processing from
+        // deadlocking.
+        let state = self.replay_kel(id)?;
+        if let Some(state) = &state {
+            self.db.update_key_state(id, state)?;
+        }
+        Ok(state)
+    }
+
+    /// Replay KEL
+    ///
+    /// Computes the current State of the given Prefix
+    /// by applying all the events of its KEL
+    fn replay_kel(&self, id: &IdentifierPrefix) -> Result<Option<IdentifierState>, Error> {
         // start with empty state
         let mut state = IdentifierState::default();
-        if let Some(events) = self.db.get_kel_finalized_events(id) {
+        if let Some(events) = self.db.get_kel_finalized_events(id)? {
             // we sort here to get inception first
             let mut sorted_events = events.collect::<Vec<TimestampedSignedEventMessage>>();
             sorted_events.sort();
@@ -81,7 +128,7 @@ impl EventProcessor {
         sn: u64,
     ) -> Result<Option<IdentifierState>, Error> {
         let mut state = IdentifierState::default();
-        if let Some(events) = self.db.get_kel_finalized_events(id) {
+        if let Some(events) = self.db.get_kel_finalized_events(id)? {
             // TODO: testing approach if events come out sorted already (as they should coz of put sequence)
             let mut sorted_events = events.collect::<Vec<TimestampedSignedEventMessage>>();
             sorted_events.sort();
@@ -107,7 +154,7 @@ impl EventProcessor {
     ) -> Result<Option<EventSeal>, Error> {
         let mut state = IdentifierState::default();
         let mut last_est = None;
-        if let Some(events) = self.db.get_kel_finalized_events(id) {
+        if let Some(events) = self.db.get_kel_finalized_events(id)? {
             for event in events {
                 state = state.apply(&event.signed_event_message.event_message.event)?;
                 // TODO: is this event.event.event stuff too ugly? =)
@@ -117,8 +164,8 @@ impl EventProcessor {
                     .event
                     .get_event_data()
                 {
-                    EventData::Icp(_) => Some(event.signed_event_message),
-                    EventData::Rot(_) => Some(event.signed_event_message),
+                    EventData::Icp(_) | EventData::Dip(_) => Some(event.signed_event_message),
+                    EventData::Rot(_) | EventData::Drt(_) => Some(event.signed_event_message),
                     _ => last_est,
                 }
             }
@@ -137,7 +184,7 @@ impl EventProcessor {
     ///
     /// Returns the current validated KEL for a given Prefix
     pub fn get_kerl(&self, id: &IdentifierPrefix) -> Result<Option<Vec<u8>>, Error> {
-        match self.db.get_kel_finalized_events(id) {
+        match self.db.get_kel_finalized_events(id)? {
             Some(events) => Ok(Some(
                 events
                     .map(|event| event.signed_event_message.serialize().unwrap_or_default())
@@ -155,7 +202,7 @@ impl EventProcessor {
     /// Returns the current Key Config associated with
     /// the given Prefix at the establishment event
     /// represented by sn and Event Digest
-    fn get_keys_at_event(
+    pub fn get_keys_at_event(
         &self,
         id: &IdentifierPrefix,
         sn: u64,
@@ -237,7 +284,7 @@ impl EventProcessor {
         sn: u64,
         validator_pref: &IdentifierPrefix,
     ) -> Result<bool, Error> {
-        Ok(if let Some(receipts) = self.db.get_receipts_t(id) {
+        Ok(if let Some(receipts) = self.db.get_receipts_t(id)? {
             receipts
                 .filter(|r| r.body.event.sn.eq(&sn))
                 .any(|receipt| receipt.validator_seal.prefix.eq(validator_pref))
@@ -277,14 +324,31 @@ impl EventProcessor {
     ///
     /// Validates a Key Event against the latest state
     /// of the Identifier and applies it to update the state
-    /// returns the updated state
+    /// returns the updated state. Events of the same Identifier
+    /// are processed one at a time. If another processor sharing the
+    /// storage changed the KEL meanwhile, the storage refuses the event
+    /// and it's validated again against the new state, a bounded number
+    /// of times.
     /// TODO improve checking and handling of errors!
     pub fn process_event(
         &self,
         signed_event: &SignedEventMessage,
     ) -> Result<Option<IdentifierState>, Error> {
-        // Log event.
+        let id = &signed_event.event_message.event.get_prefix();
+        let _lock = self.locks.lock(id)?;
+        for _ in 0..KEL_CHANGED_ATTEMPTS {
+            match self.process_event_once(signed_event) {
+                Err(Error::KelChangedError) => continue,
+                result => return result,
+            }
+        }
+        Err(Error::KelChangedError)
+    }
 
+    fn process_event_once(
+        &self,
+        signed_event: &SignedEventMessage,
+    ) -> Result<Option<IdentifierState>, Error> {
         let id = &signed_event.event_message.event.get_prefix();
 
         // If delegated event, check its delegator seal.
@@ -324,10 +388,20 @@ impl EventProcessor {
             }
             _ => Ok(()),
         }?;
-        self.apply_to_state(&signed_event.event_message)
+        let new_state = match self.apply_to_state(&signed_event.event_message) {
+            Err(Error::EventOutOfOrderError) => {
+                // keep it until the missing events are accepted
+                self.db.add_out_of_order_event(signed_event.clone(), id)?;
+                Err(Error::EventOutOfOrderError)
+            }
+            Err(Error::EventDuplicateError) => {
+                let state = self.process_duplicate_event(signed_event)?;
+                return Ok(Some(self.process_out_of_order_escrow(id)?.unwrap_or(state)));
+            }
+            new_state => new_state,
+        };
+        new_state
             .and_then(|new_state| {
-                // add event from the get go and clean it up on failure later
-                self.db.add_kel_finalized_event(signed_event.clone(), id)?;
                 // match on verification result
                 match new_state
                     .current
@@ -339,21 +413,357 @@ impl EventProcessor {
                         if !result {
                             Err(Error::SignatureVerificationError)
                         } else {
-                            // TODO should check if there are enough receipts and probably escrow
-                            Ok(new_state)
+                            Ok(())
                         }
                     }) {
-                    Ok(state) => Ok(Some(state)),
+                    Ok(()) => {
+                        self.finalize_event(EventAcceptance::new(signed_event.clone(), new_state))
+                    }
                     Err(e) => {
-                        if let Error::EventDuplicateError = e {
-                            self.db.add_duplicious_event(signed_event.clone(), id)?
+                        if let Error::NotEnoughSigsError = e {
+                            return self.escrow_partially_signed_event(signed_event, new_state);
                         };
-                        // remove last added event
-                        self.db.remove_kel_finalized_event(id, signed_event)?;
                         Err(e)
                     }
                 }
             })
+            .and_then(|state| {
+                // the event may be the one escrowed events were waiting for
+                Ok(Some(self.process_out_of_order_escrow(id)?.unwrap_or(state)))
+            })
+    }
+
+    /// Process Duplicate Event
+    ///
+    /// Handles an event with a sn already used in the KEL of its identifier.
+    /// Resubmitting an event of the KEL gives `EventDuplicateError`. A
+    /// rotation signed with the pre-rotated keys supersedes the interaction
+    /// events from its sn on, which are kept as duplicitous events for
+    /// evidence, and the resulting state is returned. Any other validly
+    /// signed conflicting event is recorded as duplicitous together with the
+    /// KEL event it conflicts with, and events that don't verify as likely
+    /// duplicitous, also together with the KEL event.
+    fn process_duplicate_event(
+        &self,
+        signed_event: &SignedEventMessage,
+    ) -> Result<IdentifierState, Error> {
+        let id = &signed_event.event_message.event.get_prefix();
+        let sn = signed_event.event_message.event.get_sn();
+        let kel_event = self
+            .get_event_at_sn(id, sn)?
+            .ok_or(Error::EventDuplicateError)?
+            .signed_event_message;
+        if kel_event.event_message.get_digest() == signed_event.event_message.get_digest() {
+            return Err(Error::EventDuplicateError);
+        }
+
+        // check the event against the KEL preceding it
+        let prior_state = match sn {
+            0 => IdentifierState::default(),
+            _ => self
+                .compute_state_at_sn(id, sn - 1)?
+                .ok_or(Error::EventOutOfOrderError)?,
+        };
+        let verified = prior_state
+            .apply(&signed_event.event_message)
+            .and_then(|new_state| {
+                let serialized = signed_event.event_message.serialize()?;
+                match new_state
+                    .current
+                    .verify(&serialized, &signed_event.signatures)?
+                {
+                    true => Ok(new_state),
+                    false => Err(Error::SignatureVerificationError),
+                }
+            });
+        let new_state = match verified {
+            Ok(new_state) => new_state,
+            Err(e) => {
+                // can't be attributed to the controller, only likely
+                // duplicitous, kept together with the KEL event it conflicts with
+                for event in [&kel_event.event_message, &signed_event.event_message] {
+                    let mut known = self
+                        .db
+                        .get_likely_duplicitous_events(id)?
+                        .into_iter()
+                        .flatten();
+                    if !known.any(|e| &e.event_message == event) {
+                        self.db.add_likely_duplicious_event(event.clone(), id)?;
+                    }
+                }
+                return Err(e);
+            }
+        };
+
+        let superseded = self
+            .db
+            .get_kel_finalized_events(id)?
+            .into_iter()
+            .flatten()
+            .map(|event| event.signed_event_message)
+            .filter(|event| event.event_message.event.get_sn() >= sn)
+            .collect::<Vec<_>>();
+        let is_recovery = matches!(
+            signed_event.event_message.event.get_event_data(),
+            EventData::Rot(_) | EventData::Drt(_)
+        ) && superseded.iter().all(|event| {
+            matches!(
+                event.event_message.event.get_event_data(),
+                EventData::Ixn(_)
+            )
+        });
+
+        let known = self
+            .db
+            .get_duplicious_events(id)?
+            .into_iter()
+            .flatten()
+            .map(|event| event.signed_event_message)
+            .collect::<Vec<_>>();
+        let is_known = |event: &SignedEventMessage| known.contains(event);
+        if is_recovery {
+            let mut acceptance = EventAcceptance::new(signed_event.clone(), new_state);
+            acceptance.duplicitous = superseded
+                .iter()
+                .filter(|event| !is_known(event))
+                .cloned()
+                .collect();
+            acceptance.superseded = superseded;
+            self.finalize_event(acceptance)
+        } else {
+            for event in [kel_event, signed_event.clone()] {
+                if !is_known(&event) {
+                    self.db.add_duplicious_event(event, id)?;
+                }
+            }
+            Err(Error::DuplicitousEventError)
+        }
+    }
+
+    /// Finalize Event
+    ///
+    /// Accepts the verified event of `acceptance` once valid receipts from
+    /// at least `tally` distinct witnesses of its state are stored, and
+    /// returns the state. Until then the event is kept in the partially
+    /// witnessed escrow. The KEL is only written to when everything has
+    /// been checked, all at once with the records accompanying the event.
+    fn finalize_event(&self, mut acceptance: EventAcceptance) -> Result<IdentifierState, Error> {
+        let signed_event = &acceptance.event;
+        let new_state = &acceptance.state;
+        let id = &signed_event.event_message.event.get_prefix();
+        if self.witnessed && new_state.tally > 0 {
+            let serialized = signed_event.event_message.serialize()?;
+            let receipts = self
+                .db
+                .get_receipts_nt(id)?
+                .into_iter()
+                .flatten()
+                .chain(self.db.get_escrow_nt_receipts(id)?.into_iter().flatten())
+                .filter(|rct| {
+                    rct.body.event.sn == signed_event.event_message.event.get_sn()
+                        && signed_event
+                            .event_message
+                            .check_digest(&rct.body.event.receipted_event_digest)
+                            .unwrap_or(false)
+                });
+            let mut witnessed_by: Vec<BasicPrefix> = vec![];
+            for (witness, signature) in receipts.flat_map(|rct| rct.couplets) {
+                if new_state.witnesses.contains(&witness)
+                    && !witnessed_by.contains(&witness)
+                    && witness.verify(&serialized, &signature).unwrap_or(false)
+                {
+                    witnessed_by.push(witness);
+                }
+            }
+            if (witnessed_by.len() as u64) < new_state.tally {
+                self.db
+                    .add_partially_witnessed_event(signed_event.clone(), id)?;
+                return Err(Error::NotEnoughReceiptsError);
+            }
+        }
+        self.process_receipts_escrow(&mut acceptance)?;
+        let state = acceptance.state.clone();
+        self.db.accept_event(acceptance)?;
+        Ok(state)
+    }
+
+    /// Process Receipts Escrow
+    ///
+    /// Checks the escrowed receipts of the event being accepted against it.
+    /// Valid receipts are moved to the receipts of the event and invalid ones
+    /// are discarded. Validator receipts stay in escrow while the KEL of
+    /// their validator is unknown.
+    fn process_receipts_escrow(&self, acceptance: &mut EventAcceptance) -> Result<(), Error> {
+        let signed_event = &acceptance.event;
+        let id = &signed_event.event_message.event.get_prefix();
+        let sn = signed_event.event_message.event.get_sn();
+        let serialized = signed_event.event_message.serialize()?;
+        let is_receipted = |receipt: &Receipt| {
+            receipt.sn == sn
+                && signed_event
+                    .event_message
+                    .check_digest(&receipt.receipted_event_digest)
+                    .unwrap_or(false)
+        };
+
+        let escrowed_nt = self.db.get_escrow_nt_receipts(id)?.into_iter().flatten();
+        for rct in escrowed_nt.filter(|rct| is_receipted(&rct.body.event)) {
+            // the same receipt may have been escrowed more than once
+            let mut stored = self.db.get_receipts_nt(id)?.into_iter().flatten();
+            if !stored.any(|r| r == rct)
+                && !acceptance.receipts_nt.contains(&rct)
+                && !rct.couplets.is_empty()
+                && rct.couplets.iter().all(|(witness, signature)| {
+                    witness.verify(&serialized, signature).unwrap_or(false)
+                })
+            {
+                acceptance.receipts_nt.push(rct.clone());
+            }
+            acceptance.escrowed_receipts_nt.push(rct);
+        }
+
+        let escrowed_t = self.db.get_escrow_t_receipts(id)?.into_iter().flatten();
+        for vrc in escrowed_t.filter(|vrc| is_receipted(&vrc.body.event)) {
+            let keys = match self.get_keys_at_event(
+                &vrc.validator_seal.prefix,
+                vrc.validator_seal.sn,
+                &vrc.validator_seal.event_digest,
+            ) {
+                Err(Error::EventOutOfOrderError) => continue,
+                keys => keys,
+            };
+            let mut stored = self.db.get_receipts_t(id)?.into_iter().flatten();
+            match keys {
+                Ok(Some(keys))
+                    if !stored.any(|r| r == vrc)
+                        && !acceptance.receipts_t.contains(&vrc)
+                        && keys.verify(&serialized, &vrc.signatures).unwrap_or(false) =>
+                {
+                    acceptance.receipts_t.push(vrc.clone())
+                }
+                _ => (),
+            }
+            acceptance.escrowed_receipts_t.push(vrc);
+        }
+        Ok(())
+    }
+
+    /// Process Partially Witnessed Escrow
+    ///
+    /// Processes the escrowed event of the given Prefix which the receipt
+    /// is made for again, so it's accepted if it has enough receipts now.
+    fn process_partially_witnessed_escrow(&self, receipt: &Receipt) -> Result<(), Error> {
+        let escrowed = self
+            .db
+            .get_partially_witnessed_events(&receipt.prefix)?
+            .and_then(|mut esc| {
+                esc.find(|e| {
+                    e.signed_event_message.event_message.event.get_sn() == receipt.sn
+                        && e.signed_event_message
+                            .event_message
+                            .check_digest(&receipt.receipted_event_digest)
+                            .unwrap_or(false)
+                })
+            });
+        if let Some(escrowed) = escrowed {
+            let event = escrowed.signed_event_message;
+            self.db
+                .remove_partially_witnessed_event(&receipt.prefix, &event)?;
+            // escrowed again while receipts are still missing, dropped if
+            // it turned invalid in the meantime
+            self.process_event(&event).ok();
+        }
+        Ok(())
+    }
+
+    /// Escrow Partially Signed Event
+    ///
+    /// Merges the signatures of the event with the ones of the same event
+    /// already in escrow. Once the merged signatures meet the threshold of
+    /// the current keys of `new_state`, the event is accepted and
+    /// `new_state` returned, until then it's kept in escrow.
+    fn escrow_partially_signed_event(
+        &self,
+        signed_event: &SignedEventMessage,
+        new_state: IdentifierState,
+    ) -> Result<IdentifierState, Error> {
+        let id = &signed_event.event_message.event.get_prefix();
+        let serialized = signed_event.event_message.serialize()?;
+        let keys = &new_state.current;
+        // don't let an invalid signature hold the event in escrow
+        if !signed_event.signatures.iter().all(|sig| {
+            keys.public_keys
+                .get(sig.index as usize)
+                .and_then(|key| key.verify(&serialized, &sig.signature).ok())
+                .unwrap_or(false)
+        }) {
+            return Err(Error::SignatureVerificationError);
+        }
+
+        let digest = signed_event.event_message.get_digest();
+        let mut merged = match self
+            .db
+            .get_partially_signed_events(id)?
+            .and_then(|mut esc| {
+                esc.find(|e| e.signed_event_message.event_message.get_digest() == digest)
+            }) {
+            Some(escrowed) => {
+                let escrowed = escrowed.signed_event_message;
+                self.db.remove_partially_signed_event(id, &escrowed)?;
+                escrowed
+            }
+            None => signed_event.clone(),
+        };
+        for sig in &signed_event.signatures {
+            if !merged.signatures.iter().any(|s| s.index == sig.index) {
+                merged.signatures.push(sig.clone());
+            }
+        }
+
+        match keys.verify(&serialized, &merged.signatures) {
+            Ok(true) => {
+                match self.finalize_event(EventAcceptance::new(merged.clone(), new_state)) {
+                    // kept for the event to be validated again
+                    Err(Error::KelChangedError) => {
+                        self.db.add_partially_signed_event(merged, id)?;
+                        Err(Error::KelChangedError)
+                    }
+                    result => result,
+                }
+            }
+            Ok(false) => Err(Error::SignatureVerificationError),
+            Err(Error::NotEnoughSigsError) => {
+                self.db.add_partially_signed_event(merged, id)?;
+                Err(Error::NotEnoughSigsError)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Process Out Of Order Escrow
+    ///
+    /// Processes escrowed events of the given Prefix that follow its
+    /// current state. Escrowed events are removed once processed, whether
+    /// they are accepted or not. Returns the state after the last accepted
+    /// event, if any.
+    pub fn process_out_of_order_escrow(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<IdentifierState>, Error> {
+        while let Some(state) = self.compute_state(id)? {
+            let next = match self.db.get_out_of_order_events(id)?.and_then(|mut esc| {
+                esc.find(|e| e.signed_event_message.event_message.event.get_sn() == state.sn + 1)
+            }) {
+                Some(next) => next.signed_event_message,
+                None => break,
+            };
+            self.db.remove_out_of_order_event(id, &next)?;
+            if let Ok(state) = self.process_event(&next) {
+                // the rest of the escrow was processed along with it
+                return Ok(state);
+            }
+        }
+        Ok(None)
     }
 
     /// Process Validator Receipt
@@ -366,6 +776,7 @@ impl EventProcessor {
         &self,
         vrc: SignedTransferableReceipt,
     ) -> Result<Option<IdentifierState>, Error> {
+        let _lock = self.locks.lock(&vrc.body.event.prefix)?;
         if let Ok(Some(event)) = self.get_event_at_sn(&vrc.body.event.prefix, vrc.body.event.sn) {
             let kp = self.get_keys_at_event(
                 &vrc.validator_seal.prefix,
@@ -402,22 +813,21 @@ impl EventProcessor {
     ) -> Result<Option<IdentifierState>, Error> {
         // get event which is being receipted
         let id = &rct.body.event.prefix.to_owned();
+        let _lock = self.locks.lock(id)?;
         if let Ok(Some(event)) = self.get_event_at_sn(&rct.body.event.prefix, rct.body.event.sn) {
-            let serialized_event = event.signed_event_message.serialize()?;
-            let (_, mut errors): (Vec<_>, Vec<Result<bool, Error>>) = rct
-                .clone()
-                .couplets
-                .into_iter()
-                .map(|(witness, receipt)| witness.verify(&serialized_event, &receipt))
-                .partition(Result::is_ok);
-            if errors.is_empty() {
-                self.db.add_receipt_nt(rct, id)?
-            } else {
-                let e = errors.pop().unwrap().unwrap_err();
-                return Err(e);
+            // receipts sign the event itself, same as in the escrow
+            let serialized_event = event.signed_event_message.event_message.serialize()?;
+            for (witness, receipt) in &rct.couplets {
+                if !witness.verify(&serialized_event, receipt)? {
+                    return Err(Error::SignatureVerificationError);
+                }
             }
+            self.db.add_receipt_nt(rct, id)?
         } else {
-            self.db.add_escrow_nt_receipt(rct, id)?
+            let receipted = rct.body.event.clone();
+            self.db.add_escrow_nt_receipt(rct, id)?;
+            // the event may have been waiting for this receipt
+            self.process_partially_witnessed_escrow(&receipted)?;
         }
         self.compute_state(id)
     }
@@ -427,7 +837,7 @@ impl EventProcessor {
         id: &IdentifierPrefix,
         sn: u64,
     ) -> Result<Option<TimestampedSignedEventMessage>, Error> {
-        if let Some(mut events) = self.db.get_kel_finalized_events(id) {
+        if let Some(mut events) = self.db.get_kel_finalized_events(id)? {
             Ok(events.find(|event| event.signed_event_message.event_message.event.get_sn() == sn))
         } else {
             Ok(None)
@@ -435,10 +845,17 @@ impl EventProcessor {
     }
 
     fn apply_to_state(&self, event: &EventMessage<KeyEvent>) -> Result<IdentifierState, Error> {
-        // get state for id (TODO cache?)
+        // get state for id
         self.compute_state(&event.event.get_prefix())
-            // get empty state if there is no state yet
-            .map(|opt| opt.map_or_else(IdentifierState::default, |s| s))
+            .and_then(|opt| match (opt, event.event.get_event_data()) {
+                (Some(state), _) => Ok(state),
+                // get empty state if there is no state yet
+                (None, EventData::Icp(_)) | (None, EventData::Dip(_)) => {
+                    Ok(IdentifierState::default())
+                }
+                // inception is missing
+                (None, _) => Err(Error::EventOutOfOrderError),
+            })
             // process the event update
             .and_then(|state| event.apply_to(state))
     }
@@ -463,7 +880,7 @@ impl EventProcessor {
         use crate::query::{reply::ReplyEvent, Route};
         let accepted_replys = self
             .db
-            .get_accepted_replys(&new_rpy.reply.event.get_prefix());
+            .get_accepted_replys(&new_rpy.reply.event.get_prefix())?;
 
         // helper function for reply timestamps checking
         fn check_dts(new_rpy: &ReplyEvent, old_rpy: &ReplyEvent) -> Result<(), Error> {
@@ -584,7 +1001,7 @@ impl EventProcessor {
 
         match self
             .db
-            .get_accepted_replys(pref)
+            .get_accepted_replys(pref)?
             .ok_or(Error::QueryError(QueryError::OutOfOrderEventError))?
             .find(|sr: &SignedReply| sr.reply.event.get_route() == Route::ReplyKsn(aid.clone()))
         {
@@ -652,7 +1069,7 @@ impl EventProcessor {
 
     #[cfg(feature = "query")]
     pub fn process_escrow(&self) -> Result<(), Error> {
-        self.db.get_all_escrowed_replys().map(|esc| {
+        self.db.get_all_escrowed_replys()?.map(|esc| {
             esc.for_each(|sig_rep| {
                 match self.process_signed_reply(&sig_rep) {
                     Ok(_)
@@ -1,23 +1,57 @@
 use super::EventProcessor;
-use crate::event::sections::seal::EventSeal;
-use crate::event_message::signed_event_message::Message;
-use crate::event_message::Digestible;
+use crate::derivation::{basic::Basic, self_signing::SelfSigning};
+use crate::event::sections::seal::{EventSeal, Seal};
+use crate::event::EventMessage;
+use crate::event_message::event_msg_builder::EventMsgBuilder;
+use crate::event_message::key_event_message::KeyEvent;
+use crate::event_message::signed_event_message::{
+    Message, SignedEventMessage, SignedNontransferableReceipt,
+};
+use crate::event_message::{Digestible, EventTypeTag};
 use crate::event_parsing::message::{signed_event_stream, signed_message};
-use crate::prefix::IdentifierPrefix;
-use crate::{database::sled::SledEventDatabase, error::Error};
+use crate::prefix::{AttachedSignaturePrefix, IdentifierPrefix};
+use crate::signer::{CryptoBox, KeyManager};
+use crate::{
+    database::{memory::MemoryEventDatabase, sled::SledEventDatabase, EventStorage},
+    error::Error,
+};
 use std::convert::TryFrom;
 use std::fs;
 use std::sync::Arc;
 
-#[test]
-fn test_process() -> Result<(), Error> {
-    use tempfile::Builder;
+/// Signs `event` with the current key of `km`, the first key of the event
+fn sign(event: EventMessage<KeyEvent>, km: &CryptoBox) -> Result<SignedEventMessage, Error> {
+    let signature = km.sign(&event.serialize()?)?;
+    Ok(event.sign(
+        vec![AttachedSignaturePrefix::new(
+            SelfSigning::Ed25519Sha512,
+            signature,
+            0,
+        )],
+        None,
+    ))
+}
 
-    // Create test db and event processor.
-    let root = Builder::new().prefix("test-db").tempdir().unwrap();
-    fs::create_dir_all(root.path()).unwrap();
+/// Inception event of the current and next key of `km`, signed by it
+fn incept(km: &CryptoBox) -> Result<SignedEventMessage, Error> {
+    let icp = EventMsgBuilder::new(EventTypeTag::Icp)
+        .with_keys(vec![Basic::Ed25519.derive(km.public_key()?)])
+        .with_next_keys(vec![Basic::Ed25519.derive(km.next_public_key()?)])
+        .build()?;
+    sign(icp, km)
+}
 
-    let db = Arc::new(SledEventDatabase::new(root.path()).unwrap());
+/// Interaction event following `prev` and anchoring `seals`
+fn interact(prev: &SignedEventMessage, seals: Vec<Seal>) -> Result<EventMessage<KeyEvent>, Error> {
+    EventMsgBuilder::new(EventTypeTag::Ixn)
+        .with_prefix(&prev.event_message.event.get_prefix())
+        .with_sn(prev.event_message.event.get_sn() + 1)
+        .with_previous_event(&prev.event_message.get_digest())
+        .with_seal(seals)
+        .build()
+}
+
+fn test_process<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
     let event_processor = EventProcessor::new(Arc::clone(&db));
     // Events and sigs are from keripy `test_multisig_digprefix` test.
     // (keripy/tests/core/test_eventing.py#1138)
@@ -125,14 +159,7 @@ fn test_process() -> Result<(), Error> {
     Ok(())
 }
 
-#[test]
-fn test_process_receipt() -> Result<(), Error> {
-    use tempfile::Builder;
-
-    // Create test db and event processor.
-    let root = Builder::new().prefix("test-db").tempdir().unwrap();
-    fs::create_dir_all(root.path()).unwrap();
-    let db = Arc::new(SledEventDatabase::new(root.path()).unwrap());
+fn test_process_receipt<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
     let event_processor = EventProcessor::new(Arc::clone(&db));
 
     // Events and sigs are from keripy `test_direct_mode` test.
@@ -168,13 +195,70 @@ fn test_process_receipt() -> Result<(), Error> {
 
     Ok(())
 }
-#[test]
-fn test_process_delegated() -> Result<(), Error> {
-    use tempfile::Builder;
-    // Create test db and event processor.
-    let root = Builder::new().prefix("test-db").tempdir().unwrap();
-    fs::create_dir_all(root.path()).unwrap();
-    let db = Arc::new(SledEventDatabase::new(root.path()).unwrap());
+
+fn test_receipts_escrow<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
+    let event_processor = EventProcessor::new(Arc::clone(&db));
+
+    // Events and receipt are the ones of `test_process_receipt`.
+    let icp_raw = br#"{"v":"KERI10JSON000120_","t":"icp","d":"EsZuhYAPBDnexP3SOl9YsGvWBrYkjYcRjomUYmCcLAYY","i":"EsZuhYAPBDnexP3SOl9YsGvWBrYkjYcRjomUYmCcLAYY","s":"0","kt":"1","k":["DSuhyBcPZEZLK-fcw5tzHn2N46wRCG_ZOoeKtWTOunRA"],"n":"EPYuj8mq_PYYsoBKkzX1kxSPGYBWaIya3slgCOyOtlqU","bt":"0","b":[],"c":[],"a":[]}-AABAAWKO9bl3OhABTaevxYiXQ1poRIGfM9ndMPq4bvrKmU_3pTN3VLNDYOI8pJBeAQxRtajQn4CSWOqgdGnmeG6fBCQ"#;
+    let icp = Message::try_from(signed_message(icp_raw).unwrap().1).unwrap();
+    let vrc_raw = br#"{"v":"KERI10JSON000091_","t":"rct","d":"EsZuhYAPBDnexP3SOl9YsGvWBrYkjYcRjomUYmCcLAYY","i":"EsZuhYAPBDnexP3SOl9YsGvWBrYkjYcRjomUYmCcLAYY","s":"0"}-FABE7pB5IKuaYh3aIWKxtexyYFhpSjDNTEGSQuxeJbWiylg0AAAAAAAAAAAAAAAAAAAAAAAE7pB5IKuaYh3aIWKxtexyYFhpSjDNTEGSQuxeJbWiylg-AABAAlIts3z2kNyis9l0Pfu54HhVN_yZHEV7NWIVoSTzl5IABelbY8xi7VRyW42ZJvBaaFTGtiqwMOywloVNpG_ZHAQ'"#;
+    let vrc = Message::try_from(signed_message(vrc_raw).unwrap().1).unwrap();
+    let val_icp_raw = br#"{"v":"KERI10JSON000120_","t":"icp","d":"E7pB5IKuaYh3aIWKxtexyYFhpSjDNTEGSQuxeJbWiylg","i":"E7pB5IKuaYh3aIWKxtexyYFhpSjDNTEGSQuxeJbWiylg","s":"0","kt":"1","k":["D8KY1sKmgyjAiUDdUBPNPyrSz_ad_Qf9yzhDNZlEKiMc"],"n":"EOWDAJvex5dZzDxeHBANyaIoUG3F4-ic81G6GwtnC4f4","bt":"0","b":[],"c":[],"a":[]}-AABAAsnbd4AkK3mlX2Z3quAfTznEPmFJInT9CE9i0aisswqaSW7QNp6XlPHo3natTevQCmS0H9J4Kb-H_V-BtpqavBA"#;
+    let val_icp = Message::try_from(signed_message(val_icp_raw).unwrap().1).unwrap();
+    let (id, rct) = match (&icp, &vrc) {
+        (Message::Event(icp), Message::TransferableRct(vrc)) => {
+            (icp.event_message.event.get_prefix(), vrc.body.clone())
+        }
+        _ => unreachable!(),
+    };
+
+    // Witness receipts, one signed by the witness and one signed by
+    // someone else.
+    let serialized_icp = match &icp {
+        Message::Event(icp) => icp.event_message.serialize()?,
+        _ => unreachable!(),
+    };
+    let witness = CryptoBox::new()?;
+    let witness_prefix = Basic::Ed25519.derive(witness.public_key()?);
+    let valid_rct = SignedNontransferableReceipt::new(
+        &rct,
+        vec![(
+            witness_prefix.clone(),
+            SelfSigning::Ed25519Sha512.derive(witness.sign(&serialized_icp)?),
+        )],
+    );
+    let invalid_rct = SignedNontransferableReceipt::new(
+        &rct,
+        vec![(
+            witness_prefix,
+            SelfSigning::Ed25519Sha512.derive(CryptoBox::new()?.sign(&serialized_icp)?),
+        )],
+    );
+
+    // Receipts of the unknown event are escrowed.
+    assert!(event_processor.process(vrc).is_err());
+    event_processor.process(Message::NontransferableRct(valid_rct.clone()))?;
+    event_processor.process(Message::NontransferableRct(invalid_rct))?;
+    assert_eq!(db.get_escrow_t_receipts(&id)?.unwrap().count(), 1);
+    assert_eq!(db.get_escrow_nt_receipts(&id)?.unwrap().count(), 2);
+
+    // Accepting the event moves the valid receipts out of escrow and
+    // discards the invalid ones.
+    event_processor.process(val_icp)?;
+    event_processor.process(icp)?;
+    assert_eq!(db.get_escrow_t_receipts(&id)?.unwrap().count(), 0);
+    assert_eq!(db.get_escrow_nt_receipts(&id)?.unwrap().count(), 0);
+    assert_eq!(db.get_receipts_t(&id)?.unwrap().count(), 1);
+    assert_eq!(
+        db.get_receipts_nt(&id)?.unwrap().collect::<Vec<_>>(),
+        vec![valid_rct]
+    );
+
+    Ok(())
+}
+
+fn test_process_delegated<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
     let event_processor = EventProcessor::new(Arc::clone(&db));
 
     // Events and sigs are from keripy `test_delegation` test.
@@ -273,16 +357,20 @@ fn test_process_delegated() -> Result<(), Error> {
         raw_parsed(deserialized_drt)?
     );
 
+    // Delegated rotation is the last establishment event of the child.
+    let last_est_seal = event_processor
+        .get_last_establishment_event_seal(&child_prefix)?
+        .unwrap();
+    assert_eq!(last_est_seal.sn, 1);
+    assert_eq!(
+        last_est_seal.event_digest,
+        drt_from_db.signed_event_message.event_message.get_digest()
+    );
+
     Ok(())
 }
 
-#[test]
-fn test_validate_seal() -> Result<(), Error> {
-    use tempfile::Builder;
-    // Create test db and event processor.
-    let root = Builder::new().prefix("test-db").tempdir().unwrap();
-    fs::create_dir_all(root.path()).unwrap();
-    let db = Arc::new(SledEventDatabase::new(root.path()).unwrap());
+fn test_validate_seal<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
     let event_processor = EventProcessor::new(Arc::clone(&db));
 
     // Events and sigs are from keripy `test_delegation` test.
@@ -329,15 +417,7 @@ fn test_validate_seal() -> Result<(), Error> {
     Ok(())
 }
 
-#[test]
-fn test_compute_state_at_sn() -> Result<(), Error> {
-    use crate::event::sections::seal::EventSeal;
-    use tempfile::Builder;
-
-    // Create test db and event processor.
-    let root = Builder::new().prefix("test-db").tempdir().unwrap();
-    fs::create_dir_all(root.path()).unwrap();
-    let db = Arc::new(SledEventDatabase::new(root.path()).unwrap());
+fn test_compute_state_at_sn<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
     let event_processor = EventProcessor::new(Arc::clone(&db));
 
     let kerl_str = br#"{"v":"KERI10JSON000120_","t":"icp","d":"EFM_0I1yFtoKJPy8L9QCN9ZBHHR-qIBSxSwHZG6uljqc","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"0","kt":"1","k":["Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30"],"n":"ESY1L4c7pxgQBuq76wUjwLdOWVfX8XLfi4unqjzBs3A4","bt":"0","b":[],"c":[],"a":[]}-AABAAqVXfmQsyme65lXrnUdx701IClRnO14wvdP00-CnTyYHetVUQEpWCS787bSNWlPG9HnroeEzfuM7ZhzM5VRCQDw{"v":"KERI10JSON000155_","t":"rot","d":"EI_rE4U5HPnLtJ-kNRBZKyTzw9dYq0yffywEoGEZZE0E","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"1","p":"EFM_0I1yFtoKJPy8L9QCN9ZBHHR-qIBSxSwHZG6uljqc","kt":"1","k":["DhSM7Cy_qC1y7jmmIu8A3lYedssBAVpHKJDfVbUXo_Nc"],"n":"EAMjC1FxUcVlPHFBcgMOTjLmlRsRNkHtXzUTFD5VaaU4","bt":"0","br":[],"ba":[],"a":[]}-AABAA6TMhDKzjpD574-xzs0A0VwD5x_VzcYcK0y9h_ttkVYQOQlocK4QpsV2kHbAHptKQg74tZxxcKuiqDg1SO9MTAA{"v":"KERI10JSON0000cb_","t":"ixn","d":"EeAgPgw8ewxtbE0zVRB92K5bLC_nmVQBgA9Ajz7TPTg0","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"2","p":"EI_rE4U5HPnLtJ-kNRBZKyTzw9dYq0yffywEoGEZZE0E","a":[]}-AABAArJjuMeasjy7gcTSZrDaVa8shiYoH4syJPXPZQMRLyaxCBFFynsWVyWrq-ZJFoWJETyX3Hi5U7AmPfWZsZfaaCw{"v":"KERI10JSON000155_","t":"rot","d":"E7YSxhPZMwGRxIP4E1POsqS7gK9jO00cE0IOr002lVPI","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"3","p":"EeAgPgw8ewxtbE0zVRB92K5bLC_nmVQBgA9Ajz7TPTg0","kt":"1","k":["D4cFZmRliumCFW5RnHvDFYCRTvNvuGMLWO1CqTaNEZZI"],"n":"Ew9LxnzhZHC6wri0dFdC5OQ_uhpAaO-wjbMtdt5ld0HQ","bt":"0","br":[],"ba":[],"a":[]}-AABAAWaOtr_k3Jk0GQn39Pc7WoZEcpeZk1m5yMScDq0yp5L4biNkSnyOA7AYO5G2n-HxZ3lM2IGeTLwN4XAdyVxRrBg{"v":"KERI10JSON000155_","t":"rot","d":"E6OMBom_RgVCE7paXEvdUBzg2rt6QRmEQ2q7Dq4FOG9o","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"4","p":"E7YSxhPZMwGRxIP4E1POsqS7gK9jO00cE0IOr002lVPI","kt":"1","k":["Dnljgftiq3x7IuF4mmMYfOzWoMNh98QDCdEU2bRSqUAQ"],"n":"EnlyNgrbZhysJ8mxSxoVuVv9QBAcB25RtVmm2A7yW7oY","bt":"0","br":[],"ba":[],"a":[]}-AABAApnOXmrsbhdRUHEg-x9CqeVKQdJIau0fTnQ8WT2uv1ueUwj7zMfWstZYEpRPkc9DAg5XqRKyMVOR2kq4sjAIpAQ{"v":"KERI10JSON0000cb_","t":"ixn","d":"ECpHwQLdPSwHBGR_QAXhlyzwyB-z8vNYuVRtTWak5kQw","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"5","p":"E6OMBom_RgVCE7paXEvdUBzg2rt6QRmEQ2q7Dq4FOG9o","a":[]}-AABAAwj0JqH6ae5vCOCxiAWmA_FKzM1g7ydxQpfgQio0Yj2DhOPKBU8kdUh0zAM2n6qi32diaJHYM15nm62Re1sK7CQ"#;
@@ -368,16 +448,483 @@ fn test_compute_state_at_sn() -> Result<(), Error> {
     Ok(())
 }
 
+fn test_partially_signed_escrow<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
+    let event_processor = EventProcessor::new(Arc::clone(&db));
+
+    // Events and sigs are from keripy `test_multisig_digprefix` test, as in
+    // `test_process`. Keys threshold is 2 of 3.
+    let icp_raw = br#"{"v":"KERI10JSON00017e_","t":"icp","d":"ELYk-z-SuTIeDncLr6GhwVUKnv3n3F1bF18qkXNd2bpk","i":"ELYk-z-SuTIeDncLr6GhwVUKnv3n3F1bF18qkXNd2bpk","s":"0","kt":"2","k":["DSuhyBcPZEZLK-fcw5tzHn2N46wRCG_ZOoeKtWTOunRA","DVcuJOOJF1IE8svqEtrSuyQjGTd2HhfAkt9y2QkUtFJI","DT1iAhBWCkvChxNWsby2J0pJyxBIxbAtbLA0Ljx-Grh8"],"n":"E9izzBkXX76sqt0N-tfLzJeRqj0W56p4pDQ_ZqNCDpyw","bt":"0","b":[],"c":[],"a":[]}-AADAA39j08U7pcU66OPKsaPExhBuHsL5rO1Pjq5zMgt_X6jRbezevis6YBUg074ZNKAGdUwHLqvPX_kse4buuuSUpAQABphobpuQEZ6EhKLhBuwgJmIQu80ZUV1GhBL0Ht47Hsl1rJiMwE2yW7-yi8k3idw2ahlpgdd9ka9QOP9yQmMWGAQACM7yfK1b86p1H62gonh1C7MECDCFBkoH0NZRjHKAEHebvd2_LLz6cpCaqKWDhbM2Rq01f9pgyDTFNLJMxkC-fAQ"#;
+    let rot_raw = br#"{"v":"KERI10JSON0001b3_","t":"rot","d":"E0UUmo4JsLq9C6LDnerxTjV0PcegpXcPsT_m2J4SeQbE","i":"ELYk-z-SuTIeDncLr6GhwVUKnv3n3F1bF18qkXNd2bpk","s":"1","p":"ELYk-z-SuTIeDncLr6GhwVUKnv3n3F1bF18qkXNd2bpk","kt":"2","k":["DKPE5eeJRzkRTMOoRGVd2m18o8fLqM2j9kaxLhV3x8AQ","D1kcBE7h0ImWW6_Sp7MQxGYSshZZz6XM7OiUE5DXm0dU","D4JDgo3WNSUpt-NG14Ni31_GCmrU0r38yo7kgDuyGkQM"],"n":"EQpRYqbID2rW8X5lB6mOzDckJEIFae6NbJISXgJSN9qg","bt":"0","br":[],"ba":[],"a":[]}-AADAATWNmB15NNCgCUeFmDv9HbSkPzZ3hK1oS4DAnBVvA1hSkBm1biGDGPIVRPMLqB_MhAy516DV7B7AQs7eoS5b1DgABOXlDXb4TktNyn_Iindz3GLwRkH_lRo3rfez107T1GfoHFetzbpx3uQExyiuiQM2JRWuHCe3wUFdhzjqQ2_MpAgACVMBC6elfrKOfs2ZQxyXrzkuxNCgpgDBPmstysWo2P6GA2epCGnKwUPq83S_g6RC6oCl9N0-DEWf7tgaD0aTcCg"#;
+    let ixn_raw = br#"{"v":"KERI10JSON0000cb_","t":"ixn","d":"E2R3qlKVg96GqkpGGaIVgjEDy_3Zklm5l0JJaI2g7lqY","i":"ELYk-z-SuTIeDncLr6GhwVUKnv3n3F1bF18qkXNd2bpk","s":"2","p":"E0UUmo4JsLq9C6LDnerxTjV0PcegpXcPsT_m2J4SeQbE","a":[]}-AADAAUHrvRANKmre1dXRNpBeJFTRBouy4Wmj72QHjBrv74JtKBq7_JzYz17A5Kem6wk5IjOi7Q3gtoxQc4a3xDXHkBwABnHvoCVgqyZZxxdVRY74SHItB8IDVK9udSY8eID7m-oktOm6mtRSbazNRq0gsCh0IwzH_-7REtFvO7CO-noQgCwACr7Re0-LgCMTtBpsq5wK7YqwSpqP6-YLu1m9IOQWv5O9zGAp-z6Qbp1x9cpMGrpTEJTHLp2PNtdTzffvztWuBBQ"#;
+    let kel = [&icp_raw[..], &rot_raw[..], &ixn_raw[..]].concat();
+    for event in signed_event_stream(&kel).unwrap().1 {
+        event_processor.process(Message::try_from(event).unwrap())?;
+    }
+
+    let ixn_raw_2 = br#"{"v":"KERI10JSON0000cb_","t":"ixn","d":"E1dzN2DTAXoC3HsdbUuiGB8nDOCYMeAtAeulBT0ljDgs","i":"ELYk-z-SuTIeDncLr6GhwVUKnv3n3F1bF18qkXNd2bpk","s":"3","p":"E2R3qlKVg96GqkpGGaIVgjEDy_3Zklm5l0JJaI2g7lqY","a":[]}-AADAAMXFghkY_dHBEDE5tHvbPu3NNwYEE8lVyYQrxvpbuXRq50jGOekJ8JoSj-_ysjD6j5Yd6fwS_h92Ie0jP-4epCQAB3CJfe8LDC3FP7RsIt4Weu0ks8IyuqeqJ_g_uko5436TONysurOp76RKisehot0SQBUcFPZ5Yp90XFb5dq661DwACJnPkO6ypAZAEkj7Wn6w0Re0Nym2mvNpxefwANJbRZPoBdAn94nKrxJW9S8jePZ49EBihU8R5j6j7Bc2rMMD7AA"#;
+    let parsed = signed_message(ixn_raw_2).unwrap().1;
+    let ixn = match Message::try_from(parsed).unwrap() {
+        Message::Event(e) => e,
+        _ => return Err(Error::SemanticError("bad deser".into())),
+    };
+    let id = ixn.event_message.event.get_prefix();
+    let signed_by = |index: usize| {
+        let mut partially_signed = ixn.clone();
+        partially_signed.signatures = vec![ixn.signatures[index].clone()];
+        Message::Event(partially_signed)
+    };
+
+    // Signatures arrive one at a time, the same one twice.
+    let state = event_processor.process(signed_by(1));
+    assert!(matches!(state, Err(Error::NotEnoughSigsError)));
+    let state = event_processor.process(signed_by(1));
+    assert!(matches!(state, Err(Error::NotEnoughSigsError)));
+    assert!(matches!(event_processor.get_event_at_sn(&id, 3), Ok(None)));
+    let escrowed = db
+        .get_partially_signed_events(&id)?
+        .unwrap()
+        .collect::<Vec<_>>();
+    assert_eq!(escrowed.len(), 1);
+    assert_eq!(escrowed[0].signed_event_message.signatures.len(), 1);
+
+    // Escrowing the event again replaces its entry.
+    let mut resigned = (*ixn).clone();
+    resigned.signatures = vec![ixn.signatures[0].clone()];
+    db.add_partially_signed_event(resigned.clone(), &id)?;
+    let escrowed = db
+        .get_partially_signed_events(&id)?
+        .unwrap()
+        .collect::<Vec<_>>();
+    assert_eq!(escrowed.len(), 1);
+    assert_eq!(escrowed[0].signed_event_message, resigned);
+
+    // The second signature meets the threshold.
+    let state = event_processor.process(signed_by(2))?.unwrap();
+    assert_eq!(state.sn, 3);
+    let ixn_from_db = event_processor.get_event_at_sn(&id, 3)?.unwrap();
+    assert_eq!(ixn_from_db.signed_event_message.signatures.len(), 2);
+    assert_eq!(db.get_partially_signed_events(&id)?.unwrap().count(), 0);
+
+    Ok(())
+}
+
+fn test_witness_threshold<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
+    use crate::event::{receipt::Receipt, SerializationFormats};
+
+    let event_processor = EventProcessor::new(Arc::clone(&db));
+
+    // Incept identifier with 3 witnesses and witness threshold of 2.
+    let km = CryptoBox::new()?;
+    let witnesses = [CryptoBox::new()?, CryptoBox::new()?, CryptoBox::new()?];
+    let witness_prefixes = witnesses
+        .iter()
+        .map(|w| Ok(Basic::Ed25519.derive(w.public_key()?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let icp = EventMsgBuilder::new(EventTypeTag::Icp)
+        .with_keys(vec![Basic::Ed25519.derive(km.public_key()?)])
+        .with_next_keys(vec![Basic::Ed25519.derive(km.next_public_key()?)])
+        .with_witness_list(&witness_prefixes)
+        .with_witness_threshold(2)
+        .build()?;
+    let signed_icp = sign(icp.clone(), &km)?;
+    let id = icp.event.get_prefix();
+    let receipt = |witness: &CryptoBox| -> Result<Message, Error> {
+        let rct = Receipt {
+            prefix: id.clone(),
+            sn: 0,
+            receipted_event_digest: icp.get_digest(),
+        }
+        .to_message(SerializationFormats::JSON)?;
+        let signature = SelfSigning::Ed25519Sha512.derive(witness.sign(&icp.serialize()?)?);
+        Ok(Message::NontransferableRct(
+            SignedNontransferableReceipt::new(
+                &rct,
+                vec![(Basic::Ed25519.derive(witness.public_key()?), signature)],
+            ),
+        ))
+    };
+
+    // A receipt arriving before the event counts once the event arrives.
+    event_processor.process(receipt(&witnesses[0])?)?;
+    let state = event_processor.process(Message::Event(Box::new(signed_icp.clone())));
+    assert!(matches!(state, Err(Error::NotEnoughReceiptsError)));
+    assert!(event_processor.compute_state(&id)?.is_none());
+
+    // Receipts of unknown witnesses and repeated receipts don't count.
+    event_processor.process(receipt(&CryptoBox::new()?)?)?;
+    event_processor.process(receipt(&witnesses[0])?)?;
+    assert!(event_processor.compute_state(&id)?.is_none());
+
+    // The second witness receipt finalizes the event.
+    let state = event_processor.process(receipt(&witnesses[2])?)?.unwrap();
+    assert_eq!(state.sn, 0);
+    assert_eq!(state.tally, 2);
+    assert_eq!(
+        event_processor
+            .get_event_at_sn(&id, 0)?
+            .unwrap()
+            .signed_event_message,
+        signed_icp
+    );
+    assert_eq!(db.get_partially_witnessed_events(&id)?.unwrap().count(), 0);
+
+    // Receipts of the accepted event are checked against it directly.
+    let stored = db.get_receipts_nt(&id)?.unwrap().count();
+    let forged = match receipt(&witnesses[1])? {
+        Message::NontransferableRct(mut rct) => {
+            let signature = SelfSigning::Ed25519Sha512.derive(witnesses[1].sign(b"forged")?);
+            rct.couplets[0].1 = signature;
+            Message::NontransferableRct(rct)
+        }
+        _ => unreachable!(),
+    };
+    let result = event_processor.process(forged);
+    assert!(matches!(result, Err(Error::SignatureVerificationError)));
+    assert_eq!(db.get_receipts_nt(&id)?.unwrap().count(), stored);
+    event_processor.process(receipt(&witnesses[1])?)?;
+    assert_eq!(db.get_receipts_nt(&id)?.unwrap().count(), stored + 1);
+
+    Ok(())
+}
+
+fn test_out_of_order_escrow<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
+    let event_processor = EventProcessor::new(Arc::clone(&db));
+
+    let kerl_str = br#"{"v":"KERI10JSON000120_","t":"icp","d":"EFM_0I1yFtoKJPy8L9QCN9ZBHHR-qIBSxSwHZG6uljqc","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"0","kt":"1","k":["Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30"],"n":"ESY1L4c7pxgQBuq76wUjwLdOWVfX8XLfi4unqjzBs3A4","bt":"0","b":[],"c":[],"a":[]}-AABAAqVXfmQsyme65lXrnUdx701IClRnO14wvdP00-CnTyYHetVUQEpWCS787bSNWlPG9HnroeEzfuM7ZhzM5VRCQDw{"v":"KERI10JSON000155_","t":"rot","d":"EI_rE4U5HPnLtJ-kNRBZKyTzw9dYq0yffywEoGEZZE0E","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"1","p":"EFM_0I1yFtoKJPy8L9QCN9ZBHHR-qIBSxSwHZG6uljqc","kt":"1","k":["DhSM7Cy_qC1y7jmmIu8A3lYedssBAVpHKJDfVbUXo_Nc"],"n":"EAMjC1FxUcVlPHFBcgMOTjLmlRsRNkHtXzUTFD5VaaU4","bt":"0","br":[],"ba":[],"a":[]}-AABAA6TMhDKzjpD574-xzs0A0VwD5x_VzcYcK0y9h_ttkVYQOQlocK4QpsV2kHbAHptKQg74tZxxcKuiqDg1SO9MTAA{"v":"KERI10JSON0000cb_","t":"ixn","d":"EeAgPgw8ewxtbE0zVRB92K5bLC_nmVQBgA9Ajz7TPTg0","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"2","p":"EI_rE4U5HPnLtJ-kNRBZKyTzw9dYq0yffywEoGEZZE0E","a":[]}-AABAArJjuMeasjy7gcTSZrDaVa8shiYoH4syJPXPZQMRLyaxCBFFynsWVyWrq-ZJFoWJETyX3Hi5U7AmPfWZsZfaaCw{"v":"KERI10JSON000155_","t":"rot","d":"E7YSxhPZMwGRxIP4E1POsqS7gK9jO00cE0IOr002lVPI","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"3","p":"EeAgPgw8ewxtbE0zVRB92K5bLC_nmVQBgA9Ajz7TPTg0","kt":"1","k":["D4cFZmRliumCFW5RnHvDFYCRTvNvuGMLWO1CqTaNEZZI"],"n":"Ew9LxnzhZHC6wri0dFdC5OQ_uhpAaO-wjbMtdt5ld0HQ","bt":"0","br":[],"ba":[],"a":[]}-AABAAWaOtr_k3Jk0GQn39Pc7WoZEcpeZk1m5yMScDq0yp5L4biNkSnyOA7AYO5G2n-HxZ3lM2IGeTLwN4XAdyVxRrBg{"v":"KERI10JSON000155_","t":"rot","d":"E6OMBom_RgVCE7paXEvdUBzg2rt6QRmEQ2q7Dq4FOG9o","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"4","p":"E7YSxhPZMwGRxIP4E1POsqS7gK9jO00cE0IOr002lVPI","kt":"1","k":["Dnljgftiq3x7IuF4mmMYfOzWoMNh98QDCdEU2bRSqUAQ"],"n":"EnlyNgrbZhysJ8mxSxoVuVv9QBAcB25RtVmm2A7yW7oY","bt":"0","br":[],"ba":[],"a":[]}-AABAApnOXmrsbhdRUHEg-x9CqeVKQdJIau0fTnQ8WT2uv1ueUwj7zMfWstZYEpRPkc9DAg5XqRKyMVOR2kq4sjAIpAQ{"v":"KERI10JSON0000cb_","t":"ixn","d":"ECpHwQLdPSwHBGR_QAXhlyzwyB-z8vNYuVRtTWak5kQw","i":"Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30","s":"5","p":"E6OMBom_RgVCE7paXEvdUBzg2rt6QRmEQ2q7Dq4FOG9o","a":[]}-AABAAwj0JqH6ae5vCOCxiAWmA_FKzM1g7ydxQpfgQio0Yj2DhOPKBU8kdUh0zAM2n6qi32diaJHYM15nm62Re1sK7CQ"#;
+    let id: IdentifierPrefix = "Ddhxr2UX8Xl55KvOd20cBYjj5QSCVqTiINgA_VJQul30".parse()?;
+    let mut events = signed_event_stream(kerl_str)
+        .unwrap()
+        .1
+        .into_iter()
+        .map(|event| Message::try_from(event).unwrap())
+        .collect::<Vec<_>>();
+    let icp = events.remove(0);
+
+    // Process the KEL backwards, every event but inception is out of order.
+    for event in events.into_iter().rev() {
+        let state = event_processor.process(event);
+        assert!(matches!(state, Err(Error::EventOutOfOrderError)));
+    }
+    assert!(event_processor.compute_state(&id)?.is_none());
+    assert_eq!(db.get_out_of_order_events(&id)?.unwrap().count(), 5);
+
+    // Inception unescrows the rest of the KEL.
+    let state = event_processor.process(icp)?.unwrap();
+    assert_eq!(state.sn, 5);
+    assert_eq!(event_processor.compute_state(&id)?, Some(state));
+    assert_eq!(event_processor.get_kerl(&id)?, Some(kerl_str.to_vec()));
+    assert_eq!(db.get_out_of_order_events(&id)?.unwrap().count(), 0);
+
+    Ok(())
+}
+
+fn test_duplicity_and_recovery<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
+    use crate::{derivation::self_addressing::SelfAddressing, event::sections::seal::DigestSeal};
+
+    let event_processor = EventProcessor::new(Arc::clone(&db));
+
+    let process = |event: &SignedEventMessage| {
+        event_processor.process(Message::Event(Box::new(event.clone())))
+    };
+
+    // Incept identifier and anchor two interaction events.
+    let mut km = CryptoBox::new()?;
+    let icp = incept(&km)?;
+    let id = icp.event_message.event.get_prefix();
+    process(&icp)?;
+    let ixn_1 = sign(interact(&icp, vec![])?, &km)?;
+    process(&ixn_1)?;
+    let ixn_2 = sign(interact(&ixn_1, vec![])?, &km)?;
+    process(&ixn_2)?;
+
+    // Resubmitting an accepted event is just a duplicate.
+    assert!(matches!(process(&ixn_2), Err(Error::EventDuplicateError)));
+    assert!(db.get_duplicious_events(&id)?.is_none());
+
+    // A conflicting interaction event signed by the current keys is
+    // duplicitous, and both versions are kept.
+    let seal = Seal::Digest(DigestSeal {
+        dig: SelfAddressing::Blake3_256.derive(b"conflicting"),
+    });
+    let conflicting_ixn = sign(interact(&icp, vec![seal])?, &km)?;
+    assert!(matches!(
+        process(&conflicting_ixn),
+        Err(Error::DuplicitousEventError)
+    ));
+    let duplicitous = db
+        .get_duplicious_events(&id)?
+        .unwrap()
+        .map(|e| e.signed_event_message)
+        .collect::<Vec<_>>();
+    assert_eq!(duplicitous, vec![ixn_1.clone(), conflicting_ixn]);
+
+    // One that isn't signed by the current keys is only likely duplicitous.
+    let seal = Seal::Digest(DigestSeal {
+        dig: SelfAddressing::Blake3_256.derive(b"forged"),
+    });
+    let forged_ixn = sign(interact(&icp, vec![seal])?, &CryptoBox::new()?)?;
+    assert!(matches!(
+        process(&forged_ixn),
+        Err(Error::SignatureVerificationError)
+    ));
+    assert_eq!(
+        db.get_likely_duplicitous_events(&id)?
+            .unwrap()
+            .map(|e| e.event_message)
+            .collect::<Vec<_>>(),
+        vec![ixn_1.event_message.clone(), forged_ixn.event_message]
+    );
+    assert_eq!(event_processor.compute_state(&id)?.unwrap().sn, 2);
+
+    // A rotation to the pre-rotated keys supersedes the interaction events
+    // from its sn on.
+    km.rotate()?;
+    let rot = EventMsgBuilder::new(EventTypeTag::Rot)
+        .with_prefix(&id)
+        .with_sn(1)
+        .with_previous_event(&icp.event_message.get_digest())
+        .with_keys(vec![Basic::Ed25519.derive(km.public_key()?)])
+        .with_next_keys(vec![Basic::Ed25519.derive(km.next_public_key()?)])
+        .build()?;
+    let rot = sign(rot, &km)?;
+    let state = process(&rot)?.unwrap();
+    assert_eq!(state.sn, 1);
+    assert_eq!(state.last_event_digest, rot.event_message.get_digest());
+    assert_eq!(event_processor.compute_state(&id)?, Some(state));
+    assert_eq!(
+        event_processor
+            .get_event_at_sn(&id, 1)?
+            .unwrap()
+            .signed_event_message,
+        rot
+    );
+    assert!(event_processor.get_event_at_sn(&id, 2)?.is_none());
+    let duplicitous = db
+        .get_duplicious_events(&id)?
+        .unwrap()
+        .map(|e| e.signed_event_message)
+        .collect::<Vec<_>>();
+    assert!(duplicitous.contains(&ixn_2));
+    assert_eq!(duplicitous.len(), 3);
+
+    // Interaction events can't supersede the rotation.
+    let late_ixn = sign(interact(&icp, vec![])?, &km)?;
+    assert!(process(&late_ixn).is_err());
+    assert_eq!(
+        event_processor
+            .get_event_at_sn(&id, 1)?
+            .unwrap()
+            .signed_event_message,
+        rot
+    );
+
+    Ok(())
+}
+
+fn test_key_state_cache<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
+    let event_processor = EventProcessor::new(Arc::clone(&db));
+
+    let mut km = CryptoBox::new()?;
+    let next = |event_type, prev: &SignedEventMessage, km: &CryptoBox| {
+        EventMsgBuilder::new(event_type)
+            .with_prefix(&prev.event_message.event.get_prefix())
+            .with_sn(prev.event_message.event.get_sn() + 1)
+            .with_previous_event(&prev.event_message.get_digest())
+            .with_keys(vec![Basic::Ed25519.derive(km.public_key()?)])
+            .with_next_keys(vec![Basic::Ed25519.derive(km.next_public_key()?)])
+            .build()
+    };
+    // The cached state is the one of a full KEL replay.
+    let check_cache = |id: &IdentifierPrefix, sn: u64| -> Result<(), Error> {
+        let state = event_processor.compute_state(id)?;
+        assert_eq!(state.as_ref().map(|s| s.sn), Some(sn));
+        assert_eq!(db.get_key_state(id)?, state);
+        assert_eq!(event_processor.replay_kel(id)?, state);
+        Ok(())
+    };
+
+    let icp = incept(&km)?;
+    let id = icp.event_message.event.get_prefix();
+    assert!(event_processor.compute_state(&id)?.is_none());
+    assert!(db.get_key_state(&id)?.is_none());
+    event_processor.process(Message::Event(Box::new(icp.clone())))?;
+    check_cache(&id, 0)?;
+
+    let ixn = sign(next(EventTypeTag::Ixn, &icp, &km)?, &km)?;
+    event_processor.process(Message::Event(Box::new(ixn.clone())))?;
+    check_cache(&id, 1)?;
+
+    km.rotate()?;
+    let rot = sign(next(EventTypeTag::Rot, &ixn, &km)?, &km)?;
+    event_processor.process(Message::Event(Box::new(rot.clone())))?;
+    check_cache(&id, 2)?;
+
+    let ixn = sign(next(EventTypeTag::Ixn, &rot, &km)?, &km)?;
+    event_processor.process(Message::Event(Box::new(ixn.clone())))?;
+    check_cache(&id, 3)?;
+
+    // Changing the KEL outside of the processor drops the cached state.
+    let unchecked_ixn = sign(next(EventTypeTag::Ixn, &ixn, &km)?, &km)?;
+    db.add_kel_finalized_event(unchecked_ixn.clone(), &id)?;
+    assert!(db.get_key_state(&id)?.is_none());
+    check_cache(&id, 4)?;
+    db.remove_kel_finalized_event(&id, &unchecked_ixn)?;
+    assert!(db.get_key_state(&id)?.is_none());
+    check_cache(&id, 3)?;
+
+    // Recovery rotation superseding the last interaction event.
+    let ixn = sign(next(EventTypeTag::Ixn, &ixn, &km)?, &km)?;
+    event_processor.process(Message::Event(Box::new(ixn.clone())))?;
+    check_cache(&id, 4)?;
+    km.rotate()?;
+    let recovery = sign(next(EventTypeTag::Rot, &rot, &km)?, &km)?;
+    event_processor.process(Message::Event(Box::new(recovery.clone())))?;
+    check_cache(&id, 3)?;
+    assert_eq!(
+        db.get_key_state(&id)?.unwrap().last_event_digest,
+        recovery.event_message.get_digest()
+    );
+
+    Ok(())
+}
+
+fn test_concurrent_processing<D>(db: Arc<D>) -> Result<(), Error>
+where
+    D: EventStorage + Send + Sync + 'static,
+{
+    use crate::{derivation::self_addressing::SelfAddressing, event::sections::seal::DigestSeal};
+    use std::{sync::Barrier, thread};
+
+    const IDENTIFIERS: usize = 8;
+    const EVENTS: u64 = 20;
+
+    let event_processor = EventProcessor::new(Arc::clone(&db));
+
+    // Two diverging KELs of interaction events signed by the controller of
+    // each identifier, all processed in parallel, each by a processor of
+    // its own over the same storage.
+    let mut branches: Vec<(IdentifierPrefix, Vec<SignedEventMessage>)> = vec![];
+    for _ in 0..IDENTIFIERS {
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        event_processor.process(Message::Event(Box::new(icp.clone())))?;
+        let id = icp.event_message.event.get_prefix();
+
+        for branch in ["left", "right"] {
+            let mut prev = icp.clone();
+            let mut events = vec![];
+            for _ in 0..EVENTS {
+                let seal = Seal::Digest(DigestSeal {
+                    dig: SelfAddressing::Blake3_256.derive(branch.as_bytes()),
+                });
+                prev = sign(interact(&prev, vec![seal])?, &km)?;
+                events.push(prev.clone());
+            }
+            branches.push((id.clone(), events));
+        }
+    }
+
+    // all the branches start at once, so their first events race
+    let start = Arc::new(Barrier::new(branches.len()));
+    let handles = branches
+        .iter()
+        .map(|(_, events)| {
+            let event_processor = EventProcessor::new(Arc::clone(&db));
+            let events = events.clone();
+            let start = Arc::clone(&start);
+            thread::spawn(move || {
+                start.wait();
+                for event in events {
+                    // events of the branch losing the race are rejected
+                    event_processor
+                        .process(Message::Event(Box::new(event)))
+                        .ok();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // No KEL forked, each one is one of its branches in full.
+    for (id, _) in &branches {
+        let kel = db
+            .get_kel_finalized_events(id)?
+            .unwrap()
+            .map(|event| event.signed_event_message)
+            .collect::<Vec<_>>();
+        assert_eq!(kel.len() as u64, EVENTS + 1);
+        assert!(branches
+            .iter()
+            .any(|(branch_id, events)| branch_id == id && &kel[1..] == events.as_slice()));
+        let state = event_processor.compute_state(id)?;
+        assert_eq!(state.as_ref().map(|s| s.sn), Some(EVENTS));
+        assert_eq!(event_processor.replay_kel(id)?, state);
+    }
+
+    // Diverging KELs of a single identifier, processed in parallel by one
+    // processor shared by all the threads.
+    let km = CryptoBox::new()?;
+    let icp = incept(&km)?;
+    event_processor.process(Message::Event(Box::new(icp.clone())))?;
+    let id = icp.event_message.event.get_prefix();
+    let branches = ["north", "east", "south", "west"]
+        .iter()
+        .map(|branch| {
+            let mut prev = icp.clone();
+            let mut events = vec![];
+            for _ in 0..EVENTS {
+                let seal = Seal::Digest(DigestSeal {
+                    dig: SelfAddressing::Blake3_256.derive(branch.as_bytes()),
+                });
+                prev = sign(interact(&prev, vec![seal])?, &km)?;
+                events.push(prev.clone());
+            }
+            Ok(events)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let event_processor = Arc::new(event_processor);
+    let start = Arc::new(Barrier::new(branches.len()));
+    let handles = branches
+        .iter()
+        .map(|events| {
+            let event_processor = Arc::clone(&event_processor);
+            let events = events.clone();
+            let start = Arc::clone(&start);
+            thread::spawn(move || {
+                start.wait();
+                for event in events {
+                    event_processor
+                        .process(Message::Event(Box::new(event)))
+                        .ok();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let kel = db
+        .get_kel_finalized_events(&id)?
+        .unwrap()
+        .map(|event| event.signed_event_message)
+        .collect::<Vec<_>>();
+    assert_eq!(kel.len() as u64, EVENTS + 1);
+    assert!(branches.iter().any(|events| &kel[1..] == events.as_slice()));
+    let state = event_processor.compute_state(&id)?;
+    assert_eq!(state.as_ref().map(|s| s.sn), Some(EVENTS));
+    assert_eq!(event_processor.replay_kel(&id)?, state);
+
+    Ok(())
+}
+
 #[cfg(feature = "query")]
-#[test]
-pub fn test_reply_escrow() -> Result<(), Error> {
+fn test_reply_escrow<D: EventStorage>(db: Arc<D>) -> Result<(), Error> {
     use crate::query::QueryError;
-    use tempfile::Builder;
 
-    // Create test db and event processor.
-    let root = Builder::new().prefix("test-db").tempdir().unwrap();
-    fs::create_dir_all(root.path()).unwrap();
-    let db = Arc::new(SledEventDatabase::new(root.path()).unwrap());
     let event_processor = EventProcessor::new(Arc::clone(&db));
 
     let identifier: IdentifierPrefix = "Et78eYkh8A3H9w6Q87EC5OcijiVEJT8KyNtEGdpPVWV8".parse()?;
@@ -402,10 +949,10 @@ pub fn test_reply_escrow() -> Result<(), Error> {
         event_processor.process(deserialized_old_rpy.clone()),
         Err(Error::QueryError(QueryError::OutOfOrderEventError))
     ));
-    let escrow = event_processor.db.get_escrowed_replys(&identifier);
+    let escrow = event_processor.db.get_escrowed_replys(&identifier)?;
     assert_eq!(escrow.unwrap().collect::<Vec<_>>().len(), 1);
 
-    let accepted_rpys = event_processor.db.get_accepted_replys(&identifier);
+    let accepted_rpys = event_processor.db.get_accepted_replys(&identifier)?;
     assert!(accepted_rpys.is_none());
 
     // process kel events and update escrow
@@ -415,10 +962,10 @@ pub fn test_reply_escrow() -> Result<(), Error> {
     });
     event_processor.process_escrow()?;
 
-    let escrow = event_processor.db.get_escrowed_replys(&identifier);
+    let escrow = event_processor.db.get_escrowed_replys(&identifier)?;
     assert_eq!(escrow.unwrap().collect::<Vec<_>>().len(), 0);
 
-    let accepted_rpys = event_processor.db.get_accepted_replys(&identifier);
+    let accepted_rpys = event_processor.db.get_accepted_replys(&identifier)?;
     assert_eq!(accepted_rpys.unwrap().collect::<Vec<_>>().len(), 1);
 
     // Try to process new out of order reply
@@ -427,14 +974,20 @@ pub fn test_reply_escrow() -> Result<(), Error> {
         event_processor.process(deserialized_new_rpy.clone()),
         Err(Error::QueryError(QueryError::OutOfOrderEventError))
     ));
-    let mut escrow = event_processor.db.get_escrowed_replys(&identifier).unwrap();
+    let mut escrow = event_processor
+        .db
+        .get_escrowed_replys(&identifier)?
+        .unwrap();
     assert_eq!(
         Message::KeyStateNotice(escrow.next().unwrap()),
         deserialized_new_rpy
     );
     assert!(escrow.next().is_none());
 
-    let mut accepted_rpys = event_processor.db.get_accepted_replys(&identifier).unwrap();
+    let mut accepted_rpys = event_processor
+        .db
+        .get_accepted_replys(&identifier)?
+        .unwrap();
     assert_eq!(
         Message::KeyStateNotice(accepted_rpys.next().unwrap()),
         deserialized_old_rpy
@@ -448,10 +1001,13 @@ pub fn test_reply_escrow() -> Result<(), Error> {
     });
     event_processor.process_escrow()?;
 
-    let escrow = event_processor.db.get_escrowed_replys(&identifier);
+    let escrow = event_processor.db.get_escrowed_replys(&identifier)?;
     assert_eq!(escrow.unwrap().collect::<Vec<_>>().len(), 0);
 
-    let mut accepted_rpys = event_processor.db.get_accepted_replys(&identifier).unwrap();
+    let mut accepted_rpys = event_processor
+        .db
+        .get_accepted_replys(&identifier)?
+        .unwrap();
     assert_eq!(
         Message::KeyStateNotice(accepted_rpys.next().unwrap()),
         deserialized_new_rpy
@@ -488,3 +1044,69 @@ pub fn test_query() -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Runs each of the given tests against Sled and in-memory databases
+macro_rules! database_tests {
+    ($($(#[$attr:meta])* $test:ident,)*) => {
+        mod sled_db {
+            use super::*;
+            use tempfile::Builder;
+
+            $(
+                $(#[$attr])*
+                #[test]
+                fn $test() -> Result<(), Error> {
+                    let root = Builder::new().prefix("test-db").tempdir().unwrap();
+                    fs::create_dir_all(root.path()).unwrap();
+                    super::$test(Arc::new(SledEventDatabase::new(root.path()).unwrap()))
+                }
+            )*
+        }
+
+        mod memory_db {
+            use super::*;
+
+            $(
+                $(#[$attr])*
+                #[test]
+                fn $test() -> Result<(), Error> {
+                    super::$test(Arc::new(MemoryEventDatabase::new()))
+                }
+            )*
+        }
+
+        #[cfg(feature = "sqlite-db")]
+        mod sqlite_db {
+            use super::*;
+            use crate::database::sqlite::SqliteEventDatabase;
+            use tempfile::Builder;
+
+            $(
+                $(#[$attr])*
+                #[test]
+                fn $test() -> Result<(), Error> {
+                    let root = Builder::new().prefix("test-db").tempdir().unwrap();
+                    let path = root.path().join("events.db");
+                    super::$test(Arc::new(SqliteEventDatabase::new(path.as_path()).unwrap()))
+                }
+            )*
+        }
+    };
+}
+
+database_tests! {
+    test_process,
+    test_process_receipt,
+    test_receipts_escrow,
+    test_process_delegated,
+    test_validate_seal,
+    test_compute_state_at_sn,
+    test_partially_signed_escrow,
+    test_witness_threshold,
+    test_out_of_order_escrow,
+    test_duplicity_and_recovery,
+    test_key_state_cache,
+    test_concurrent_processing,
+    #[cfg(feature = "query")]
+    test_reply_escrow,
+}
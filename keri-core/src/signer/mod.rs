@@ -14,6 +14,7 @@ pub trait KeyManager {
     fn rotate(&mut self) -> Result<(), Error>;
 }
 
+#[derive(Clone)]
 pub struct CryptoBox {
     signer: Signer,
     next_priv_key: PrivateKey,
@@ -60,6 +61,7 @@ impl CryptoBox {
     }
 }
 
+#[derive(Clone)]
 struct Signer {
     priv_key: PrivateKey,
     pub pub_key: PublicKey,
@@ -0,0 +1,70 @@
+//! Copy KELs between a Sled database and git storage
+//!
+//! Usage: `keri-migrate <to-git|to-sled> <sled-path> <git-storage-path>`
+
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use git_storage::{signature::UserInfo, Write};
+use keri::database::sled::SledEventDatabase;
+use keri::processor::EventProcessor;
+use keri_git::{gitdb::GitStorageDatabase, migrate};
+
+const USAGE: &str = "usage: keri-migrate <to-git|to-sled> <sled-path> <git-storage-path>";
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (direction, sled_path, git_path) = match args.as_slice() {
+        [direction, sled_path, git_path] => (direction, sled_path, git_path),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(direction, sled_path, git_path) {
+        Ok(report) => {
+            print!("{}", report);
+            if report.skipped.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(e) => {
+            eprintln!("keri-migrate: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(
+    direction: &str,
+    sled_path: &str,
+    git_path: &str,
+) -> Result<migrate::Report, Box<dyn std::error::Error>> {
+    let info = UserInfo {
+        name: "keri-migrate".to_owned(),
+        email: "keri-migrate@localhost".to_owned(),
+    };
+    let storage = Write::open(git_path, info)?;
+    let git = GitStorageDatabase::open(&storage)?;
+    let sled = Arc::new(SledEventDatabase::new(Path::new(sled_path))?);
+
+    match direction {
+        "to-git" => {
+            // Events are validated against a scratch database before being
+            // written to git
+            let scratch = std::env::temp_dir().join(format!("keri-migrate-{}", std::process::id()));
+            let validator =
+                EventProcessor::new(Arc::new(SledEventDatabase::new(scratch.as_path())?));
+            let report = migrate::sled_to_git(&sled, &git, &validator)?;
+            drop(validator);
+            std::fs::remove_dir_all(&scratch)?;
+            Ok(report)
+        }
+        "to-sled" => Ok(migrate::git_to_sled(&git, &EventProcessor::new(sled))?),
+        _ => Err(USAGE.into()),
+    }
+}
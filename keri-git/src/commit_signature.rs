@@ -0,0 +1,381 @@
+use git_commit::{Commit, Headers};
+
+use keri::derivation::{basic::Basic, self_signing::SelfSigning};
+use keri::event::{
+    event_data::EventData,
+    sections::{key_config::KeyConfig, seal::EventSeal},
+};
+use keri::event_parsing::{attachment::attachment, Attachment};
+use keri::prefix::{AttachedSignaturePrefix, IdentifierPrefix, Prefix};
+use keri::processor::EventProcessor;
+use keri::signer::KeyManager;
+
+/// Commit header carrying the KERI signature of the commit
+pub const SIGNATURE_HEADER: &str = "keri-signature";
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Parse {
+        #[error("Missing {0} in KERI signature header")]
+        Missing(&'static str),
+        #[error("Signature is not a single seal signatures group")]
+        Attachment,
+        #[error("Signer does not match the identifier of the seal")]
+        SignerMismatch,
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Sign {
+        #[error("No establishment event known for the signer")]
+        UnknownSigner,
+        #[error("Key of the key manager is not a current key of the signer")]
+        UnknownKey,
+        #[error("Commit is signed by another identifier or establishment event")]
+        OtherSigner,
+        #[error(transparent)]
+        Parse(#[from] Parse),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Verify {
+        #[error("Commit has no KERI signature")]
+        Unsigned,
+        #[error("Commit has more than one KERI signature")]
+        Ambiguous,
+        #[error("Seal does not point to an establishment event of the signer KEL")]
+        UnknownSeal,
+        #[error(transparent)]
+        Parse(#[from] Parse),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+    }
+}
+
+/// Signature of a commit by a KERI identifier
+///
+/// The signing keys are the ones established by the event `seal` points to,
+/// so the signature stays verifiable after the signer rotates its keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommitSignature {
+    pub signer: IdentifierPrefix,
+    pub seal: EventSeal,
+    pub signatures: Vec<AttachedSignaturePrefix>,
+}
+
+impl CommitSignature {
+    /// Header value: `<signer> <seal signatures group>`
+    pub fn to_header(&self) -> String {
+        let attachment =
+            Attachment::SealSignaturesGroups(vec![(self.seal.clone(), self.signatures.clone())]);
+        format!("{} {}", self.signer.to_str(), attachment.to_cesr())
+    }
+
+    pub fn from_header(value: &str) -> Result<Self, error::Parse> {
+        let (signer, group) = value
+            .split_once(' ')
+            .ok_or(error::Parse::Missing("signature"))?;
+        let signer: IdentifierPrefix = signer.parse()?;
+        let (seal, signatures) = match attachment(group.trim().as_bytes()) {
+            Ok((rest, Attachment::SealSignaturesGroups(mut groups)))
+                if rest.is_empty() && groups.len() == 1 =>
+            {
+                groups.remove(0)
+            }
+            _ => return Err(error::Parse::Attachment),
+        };
+        if seal.prefix != signer {
+            return Err(error::Parse::SignerMismatch);
+        }
+
+        Ok(Self {
+            signer,
+            seal,
+            signatures,
+        })
+    }
+}
+
+/// Sign `commit` as `signer` with the current key of `key_manager`
+///
+/// The signature is made over the commit without KERI signature headers and
+/// added to it as a `SIGNATURE_HEADER` header. Signatures of the other keys
+/// of a multi-key signer already on the commit are kept in the same header,
+/// so the threshold can be met by signing the commit once per key. The KEL
+/// of `signer` must be known to `processor`.
+pub fn sign<K: KeyManager>(
+    commit: &Commit,
+    signer: &IdentifierPrefix,
+    key_manager: &K,
+    processor: &EventProcessor,
+) -> Result<Commit, error::Sign> {
+    let seal = processor
+        .get_last_establishment_event_seal(signer)?
+        .ok_or(error::Sign::UnknownSigner)?;
+    let keys = establishment_keys(processor, &seal)?.ok_or(error::Sign::UnknownSigner)?;
+    // The key manager may hold any of the keys of a multi-key identifier
+    let key = Basic::Ed25519.derive(key_manager.public_key()?);
+    let index = keys
+        .public_keys
+        .iter()
+        .position(|k| k == &key)
+        .ok_or(error::Sign::UnknownKey)? as u16;
+
+    let mut signature = CommitSignature {
+        signer: signer.clone(),
+        seal,
+        signatures: vec![],
+    };
+    for value in commit.values(SIGNATURE_HEADER) {
+        let signed = CommitSignature::from_header(value)?;
+        if signed.signer != signature.signer || signed.seal != signature.seal {
+            return Err(error::Sign::OtherSigner);
+        }
+        for sig in signed.signatures {
+            if sig.index != index && !signature.signatures.iter().any(|s| s.index == sig.index) {
+                signature.signatures.push(sig);
+            }
+        }
+    }
+
+    let unsigned = unsigned(commit);
+    signature.signatures.push(AttachedSignaturePrefix::new(
+        SelfSigning::Ed25519Sha512,
+        key_manager.sign(unsigned.to_string().as_bytes())?,
+        index,
+    ));
+
+    let mut headers = Headers::new();
+    for (name, value) in unsigned.headers() {
+        headers.push(name, value);
+    }
+    headers.push(SIGNATURE_HEADER, &signature.to_header());
+    Ok(with_headers(&unsigned, headers))
+}
+
+/// Verify the KERI signature of `commit`
+///
+/// The signatures are checked against the keys established by the event of
+/// the signer KEL the signature seal points to. The KEL of the signer must
+/// be known to `processor`. Returns the signature if it is valid.
+pub fn verify(
+    commit: &Commit,
+    processor: &EventProcessor,
+) -> Result<Option<CommitSignature>, error::Verify> {
+    let mut values = commit.values(SIGNATURE_HEADER);
+    let signature = CommitSignature::from_header(values.next().ok_or(error::Verify::Unsigned)?)?;
+    if values.next().is_some() {
+        return Err(error::Verify::Ambiguous);
+    }
+
+    let keys = establishment_keys(processor, &signature.seal)?.ok_or(error::Verify::UnknownSeal)?;
+    match keys.verify(
+        unsigned(commit).to_string().as_bytes(),
+        &signature.signatures,
+    ) {
+        Ok(true) => Ok(Some(signature)),
+        Ok(false) | Err(keri::error::Error::NotEnoughSigsError) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Keys established by the event `seal` points to, `None` if it isn't an
+/// establishment event of the KEL
+fn establishment_keys(
+    processor: &EventProcessor,
+    seal: &EventSeal,
+) -> Result<Option<KeyConfig>, keri::error::Error> {
+    let event = match processor.get_event_at_sn(&seal.prefix, seal.sn)? {
+        Some(event) => event.signed_event_message.event_message,
+        None => return Ok(None),
+    };
+    if !event.check_digest(&seal.event_digest)? {
+        return Ok(None);
+    }
+    Ok(match event.event.get_event_data() {
+        EventData::Icp(icp) => Some(icp.key_config),
+        EventData::Rot(rot) | EventData::Drt(rot) => Some(rot.key_config),
+        EventData::Dip(dip) => Some(dip.inception_data.key_config),
+        EventData::Ixn(_) => None,
+    })
+}
+
+/// `commit` without its KERI signature headers, the signed payload
+fn unsigned(commit: &Commit) -> Commit {
+    let mut headers = Headers::new();
+    for (name, value) in commit
+        .headers()
+        .filter(|(name, _)| *name != SIGNATURE_HEADER)
+    {
+        headers.push(name, value);
+    }
+    with_headers(commit, headers)
+}
+
+fn with_headers(commit: &Commit, headers: Headers) -> Commit {
+    Commit::new(
+        commit.tree(),
+        commit.parents(),
+        commit.author().clone(),
+        commit.committer().clone(),
+        headers,
+        commit.message().to_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use keri::{
+        database::sled::SledEventDatabase,
+        event::{sections::threshold::SignatureThreshold, EventMessage},
+        event_message::{
+            event_msg_builder::EventMsgBuilder,
+            key_event_message::KeyEvent,
+            signed_event_message::{Message, SignedEventMessage},
+            EventTypeTag,
+        },
+        signer::CryptoBox,
+    };
+    use tempfile::Builder;
+
+    use super::*;
+
+    const COMMIT: &str = "\
+tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author Alice Liddell <alice@example.com> 1660000000 +0000
+committer Alice Liddell <alice@example.com> 1660000000 +0000
+
+Add the project description
+";
+
+    type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+    fn signed(event: EventMessage<KeyEvent>, kms: &[&CryptoBox]) -> Result<SignedEventMessage> {
+        let serialized = event.serialize()?;
+        let signatures = kms
+            .iter()
+            .enumerate()
+            .map(|(index, km)| {
+                Ok(AttachedSignaturePrefix::new(
+                    SelfSigning::Ed25519Sha512,
+                    km.sign(&serialized)?,
+                    index as u16,
+                ))
+            })
+            .collect::<std::result::Result<Vec<_>, keri::error::Error>>()?;
+        Ok(event.sign(signatures, None))
+    }
+
+    /// Incepts an identifier with a key of each of `kms`, all of them
+    /// required to sign
+    fn incept(processor: &EventProcessor, kms: &[&CryptoBox]) -> Result<SignedEventMessage> {
+        let icp = EventMsgBuilder::new(EventTypeTag::Icp)
+            .with_keys(
+                kms.iter()
+                    .map(|km| Ok(Basic::Ed25519.derive(km.public_key()?)))
+                    .collect::<std::result::Result<_, keri::error::Error>>()?,
+            )
+            .with_next_keys(
+                kms.iter()
+                    .map(|km| Ok(Basic::Ed25519.derive(km.next_public_key()?)))
+                    .collect::<std::result::Result<_, keri::error::Error>>()?,
+            )
+            .with_threshold(&SignatureThreshold::Simple(kms.len() as u64))
+            .with_next_threshold(&SignatureThreshold::Simple(kms.len() as u64))
+            .build()?;
+        let icp = signed(icp, kms)?;
+        processor.process(Message::Event(Box::new(icp.clone())))?;
+        Ok(icp)
+    }
+
+    #[test]
+    fn test_sign_rotate_verify() -> Result<()> {
+        let root = Builder::new().prefix("test-db").tempdir()?;
+        let processor = EventProcessor::new(Arc::new(SledEventDatabase::new(root.path())?));
+        let mut km = CryptoBox::new()?;
+        let icp = incept(&processor, &[&km])?;
+        let id = icp.event_message.event.get_prefix();
+
+        let commit: Commit = COMMIT.parse()?;
+        let signed_commit = sign(&commit, &id, &km, &processor)?;
+        let signature = verify(&signed_commit, &processor)?.expect("valid signature");
+        assert_eq!(signature.seal.sn, 0);
+
+        // Signatures by rotated out keys stay valid, new ones are made with
+        // the keys of the rotation.
+        km.rotate()?;
+        let rot = EventMsgBuilder::new(EventTypeTag::Rot)
+            .with_prefix(&id)
+            .with_sn(1)
+            .with_previous_event(&icp.event_message.get_digest())
+            .with_keys(vec![Basic::Ed25519.derive(km.public_key()?)])
+            .with_next_keys(vec![Basic::Ed25519.derive(km.next_public_key()?)])
+            .build()?;
+        processor.process(Message::Event(Box::new(signed(rot, &[&km])?)))?;
+        assert_eq!(verify(&signed_commit, &processor)?, Some(signature));
+        let resigned = sign(&commit, &id, &km, &processor)?;
+        assert_eq!(
+            verify(&resigned, &processor)?.map(|signature| signature.seal.sn),
+            Some(1)
+        );
+
+        assert!(matches!(
+            verify(&commit, &processor),
+            Err(error::Verify::Unsigned)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampered_commit() -> Result<()> {
+        let root = Builder::new().prefix("test-db").tempdir()?;
+        let processor = EventProcessor::new(Arc::new(SledEventDatabase::new(root.path())?));
+        let km = CryptoBox::new()?;
+        let id = incept(&processor, &[&km])?.event_message.event.get_prefix();
+
+        let signed_commit = sign(&COMMIT.parse()?, &id, &km, &processor)?;
+        let tampered: Commit = signed_commit
+            .to_string()
+            .replace(
+                "Add the project description",
+                "Remove the project description",
+            )
+            .parse()?;
+        assert_eq!(verify(&tampered, &processor)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_key_signature() -> Result<()> {
+        let root = Builder::new().prefix("test-db").tempdir()?;
+        let processor = EventProcessor::new(Arc::new(SledEventDatabase::new(root.path())?));
+        let (km0, km1) = (CryptoBox::new()?, CryptoBox::new()?);
+        let id = incept(&processor, &[&km0, &km1])?
+            .event_message
+            .event
+            .get_prefix();
+
+        // Each key adds its signature to the same header.
+        let commit = sign(&COMMIT.parse()?, &id, &km0, &processor)?;
+        assert_eq!(verify(&commit, &processor)?, None);
+        let commit = sign(&commit, &id, &km1, &processor)?;
+        assert_eq!(commit.values(SIGNATURE_HEADER).count(), 1);
+        let signature = verify(&commit, &processor)?.expect("valid signature");
+        assert_eq!(signature.signatures.len(), 2);
+
+        // Signing again replaces the signature of the key.
+        let commit = sign(&commit, &id, &km1, &processor)?;
+        assert_eq!(
+            verify(&commit, &processor)?.map(|signature| signature.signatures.len()),
+            Some(2)
+        );
+        Ok(())
+    }
+}
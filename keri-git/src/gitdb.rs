@@ -1,18 +1,35 @@
-use git_storage::signature::UserInfo;
-
 use std::path::{Path, PathBuf};
 
-use git_storage::Write;
+use git_storage::{Commit, Write};
 
+#[cfg(feature = "query")]
+use keri::query::reply::SignedReply;
 use keri::{
-    database::EventDatabase,
+    database::{DbIter, EventAcceptance, EventDatabase, EventStorage},
+    derivation::self_addressing::SelfAddressing,
+    error::Error,
+    event::{
+        event_data::EventData, receipt::Receipt, sections::seal::EventSeal, EventMessage,
+        SerializationFormats,
+    },
+    event_message::{
+        key_event_message::KeyEvent,
+        signed_event_message::{
+            Message, SignedEventMessage, SignedNontransferableReceipt, SignedTransferableReceipt,
+            TimestampedSignedEventMessage,
+        },
+        TimestampedEventMessage,
+    },
+    event_parsing::{message::signed_message, Attachment, SignedEventData},
     prefix::{
-        AttachedSignaturePrefix, BasicPrefix, IdentifierPrefix, SelfAddressingPrefix,
+        AttachedSignaturePrefix, BasicPrefix, IdentifierPrefix, Prefix, SelfAddressingPrefix,
         SelfSigningPrefix,
     },
+    processor::EventProcessor,
+    state::IdentifierState,
 };
 
-use crate::keri_store::KeriStore;
+use crate::keri_store::{self, KeriStore, KERI_EVENT_ENTRY};
 
 pub mod error {
     use thiserror::Error;
@@ -31,6 +48,102 @@ pub mod error {
     pub enum KeriError {
         #[error(transparent)]
         KeriError(#[from] crate::keri_store::error::KeriError),
+        #[error(transparent)]
+        Integrity(#[from] crate::keri_store::error::Integrity),
+        #[error(transparent)]
+        FindEntry(#[from] crate::keri_store::error::FindEntry),
+        #[error(transparent)]
+        Append(#[from] crate::keri_store::error::Append),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+        #[error("Event {0} was not logged")]
+        UnknownEvent(String),
+        #[error("Stored entry {0} is not a KERI event")]
+        NotAnEvent(String),
+        #[error("Stored entry {0} is not a KERI receipt")]
+        NotAReceipt(String),
+        #[error("Stored entry {0} is not a KERI message")]
+        NotAMessage(String),
+    }
+
+    // `EventStorage` only speaks `keri::error::Error`, KERI errors are
+    // passed through and storage errors described
+    impl From<KeriError> for keri::error::Error {
+        fn from(e: KeriError) -> Self {
+            match e {
+                KeriError::Keri(e) => e,
+                e => keri::error::Error::BackendError(e.to_string()),
+            }
+        }
+    }
+}
+
+/// KERI ref directories, relative to `keri_store::KERI_REFS`
+mod refs {
+    pub const EVENTS: &str = "events";
+    pub const PARTIALLY_SIGNED: &str = "escrow/partially-signed";
+    pub const PARTIALLY_WITNESSED: &str = "escrow/partially-witnessed";
+    pub const OUT_OF_ORDER: &str = "escrow/out-of-order";
+    pub const LIKELY_DUPLICITOUS: &str = "duplicity/likely";
+    pub const DUPLICITOUS: &str = "duplicity/known";
+    pub const NT_RECEIPTS: &str = "receipts/nt";
+    pub const T_RECEIPTS: &str = "receipts/t";
+    pub const ESCROWED_NT_RECEIPTS: &str = "escrow/nt-receipts";
+    pub const ESCROWED_T_RECEIPTS: &str = "escrow/t-receipts";
+    #[cfg(feature = "query")]
+    pub const ACCEPTED_REPLIES: &str = "replies/accepted";
+    #[cfg(feature = "query")]
+    pub const ESCROWED_REPLIES: &str = "escrow/replies";
+}
+
+/// Outcome of reconciling a fetched KEL with the local one
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reconciliation {
+    /// The remote KEL extended the local one, which now ends at `sn`
+    FastForward { sn: u64 },
+    /// The remote KEL is the local one or a prefix of it
+    UpToDate,
+    /// The two KELs hold different events at the same sn
+    Duplicity(DuplicityReport),
+}
+
+/// Two different events of an identifier at the same sn
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicityReport {
+    pub prefix: IdentifierPrefix,
+    pub sn: u64,
+    pub local: SelfAddressingPrefix,
+    pub remote: SelfAddressingPrefix,
+    /// Whether the remote event is signed by the keys in control at `sn`.
+    /// If not, it is only likely duplicitous.
+    pub verified: bool,
+}
+
+/// Process `event` into `processor`
+///
+/// A witnessed event is only accepted once enough witness `receipts` are
+/// known, so they are processed as well if the event lacks them.
+pub fn process_witnessed(
+    processor: &EventProcessor,
+    event: &SignedEventMessage,
+    receipts: impl IntoIterator<Item = SignedNontransferableReceipt>,
+) -> Result<(), Error> {
+    match processor.process(Message::Event(Box::new(event.clone()))) {
+        Err(Error::NotEnoughReceiptsError) => (),
+        outcome => return outcome.map(|_| ()),
+    };
+    for rct in receipts.into_iter().filter(|rct| {
+        rct.body.event.sn == event.event_message.event.get_sn()
+            && rct.body.event.receipted_event_digest == event.event_message.get_digest()
+    }) {
+        processor.process(Message::NontransferableRct(rct))?;
+    }
+    match processor.get_event_at_sn(
+        &event.event_message.event.get_prefix(),
+        event.event_message.event.get_sn(),
+    )? {
+        Some(accepted) if accepted.signed_event_message == *event => Ok(()),
+        _ => Err(Error::NotEnoughReceiptsError),
     }
 }
 
@@ -45,8 +158,7 @@ impl<'k> GitStorageDatabase<'k> {
         })
     }
 
-    // Use UserInfo to derive applicable namespace ?
-    pub fn init<P: AsRef<Path>>(path: P, info: UserInfo) -> Result<(), error::IO> {
+    pub fn init<P: AsRef<Path>>(path: P) -> Result<(), error::IO> {
         let keri_path = Self::keri_dir(&path);
 
         std::fs::create_dir_all(keri_path)?;
@@ -67,43 +179,600 @@ impl<'k> GitStorageDatabase<'k> {
         let keri_path = Self::keri_dir(&path);
         keri_path.is_dir()
     }
+
+    /// Identifiers whose KEL is held by the storage
+    pub fn identifiers(&self) -> Result<Vec<IdentifierPrefix>, error::KeriError> {
+        Ok(self.storage.identifiers()?)
+    }
+
+    /// Finalised events of `pref`, oldest first
+    pub fn kel(
+        &self,
+        pref: &IdentifierPrefix,
+    ) -> Result<Vec<SignedEventMessage>, error::KeriError> {
+        let kel = match self.storage.log_entries(pref, None) {
+            Ok(kel) => kel,
+            Err(keri_store::error::KeriError::FindRef(keri_store::error::FindEntry::Empty)) => {
+                return Ok(vec![])
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(kel
+            .into_iter()
+            .rev()
+            .map(|e| e.signed_event_message)
+            .collect())
+    }
+
+    /// Logged (not necessarily finalised) event with digest `dig`
+    pub fn logged_event(
+        &self,
+        pref: &IdentifierPrefix,
+        dig: &SelfAddressingPrefix,
+    ) -> Result<Option<SignedEventMessage>, error::KeriError> {
+        let name = format!("{}/{}", refs::EVENTS, dig.to_str());
+        match self.storage.entry(pref, &name, KERI_EVENT_ENTRY)? {
+            Some(raw) => match signed_message(&raw).map(|(_, data)| Message::try_from(data)) {
+                Ok(Ok(Message::Event(ev))) => Ok(Some(*ev)),
+                _ => Err(error::KeriError::NotAnEvent(name)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Reconcile the KEL of `pref` with the fetched KEL ending at `remote`
+    ///
+    /// The local KEL is fast-forwarded when `remote` extends it, once the new
+    /// events are validated by `processor`, and left as is when `remote` is
+    /// a prefix of it. When both KELs hold different events at the same sn,
+    /// the remote event is logged and marked as duplicitous, or likely
+    /// duplicitous if its signatures don't verify, and reported.
+    pub fn reconcile(
+        &self,
+        processor: &EventProcessor,
+        pref: &IdentifierPrefix,
+        remote: &Commit,
+    ) -> Result<Reconciliation, error::KeriError> {
+        let mut local = match self.storage.log_entries(pref, None) {
+            Ok(local) => local,
+            Err(keri_store::error::KeriError::FindRef(keri_store::error::FindEntry::Empty)) => {
+                vec![]
+            }
+            Err(e) => return Err(e.into()),
+        };
+        local.reverse();
+        let mut fetched = self.storage.log_entries(pref, Some(remote.clone()))?;
+        fetched.reverse();
+
+        // Replay the local KEL into the processor first, so the remote
+        // events after it are validated against the local key state
+        for e in &local {
+            match self.process_event(processor, &e.signed_event_message) {
+                Ok(_) | Err(error::KeriError::Keri(Error::EventDuplicateError)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        for (l, r) in local.iter().zip(&fetched) {
+            let (l, r) = (&l.signed_event_message, &r.signed_event_message);
+            if l.event_message.get_digest() != r.event_message.get_digest() {
+                return Ok(Reconciliation::Duplicity(
+                    self.report_duplicity(processor, l, r)?,
+                ));
+            }
+        }
+
+        if fetched.len() <= local.len() {
+            return Ok(Reconciliation::UpToDate);
+        }
+        // The fetched commits become ours, so the whole chain is checked
+        let fetched = self.storage.verified_log(pref, Some(remote.clone()))?;
+        let new = &fetched[local.len()..];
+        for e in new {
+            self.process_event(processor, e)?;
+        }
+        match self.storage.fast_forward(pref, remote) {
+            Ok(_) => (),
+            // Same events were committed separately, copy the new ones over
+            Err(keri_store::error::Append::NotFastForward) => {
+                for e in new {
+                    self.storage.append(e)?;
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(Reconciliation::FastForward {
+            sn: fetched.len() as u64 - 1,
+        })
+    }
+
+    /// Process `event` into `processor` along with the witness receipts
+    /// stored for it
+    fn process_event(
+        &self,
+        processor: &EventProcessor,
+        event: &SignedEventMessage,
+    ) -> Result<(), error::KeriError> {
+        let receipts = self.nt_receipts(
+            &event.event_message.event.get_prefix(),
+            event.event_message.event.get_sn(),
+            &event.event_message.get_digest(),
+        )?;
+        Ok(process_witnessed(processor, event, receipts)?)
+    }
+
+    /// Digests and sns of events escrowed as partially signed
+    pub fn partially_signed_events(
+        &self,
+        pref: &IdentifierPrefix,
+    ) -> Result<Vec<(SelfAddressingPrefix, u64)>, error::KeriError> {
+        self.marked_events(pref, refs::PARTIALLY_SIGNED)
+    }
+
+    /// Digests and sns of events escrowed as out of order
+    pub fn out_of_order_events(
+        &self,
+        pref: &IdentifierPrefix,
+    ) -> Result<Vec<(SelfAddressingPrefix, u64)>, error::KeriError> {
+        self.marked_events(pref, refs::OUT_OF_ORDER)
+    }
+
+    /// Digests and sns of events marked as likely duplicitous
+    pub fn likely_duplicitous_events(
+        &self,
+        pref: &IdentifierPrefix,
+    ) -> Result<Vec<(SelfAddressingPrefix, u64)>, error::KeriError> {
+        self.marked_events(pref, refs::LIKELY_DUPLICITOUS)
+    }
+
+    /// Digests and sns of events marked as duplicitous
+    pub fn duplicitous_events(
+        &self,
+        pref: &IdentifierPrefix,
+    ) -> Result<Vec<(SelfAddressingPrefix, u64)>, error::KeriError> {
+        self.marked_events(pref, refs::DUPLICITOUS)
+    }
+
+    /// Store a nontransferable (witness) receipt
+    ///
+    /// Each couplet is kept as its own receipt under
+    /// `receipts/nt/<sn>/<digest>` of the receipted identifier, in an entry
+    /// named after the signer.
+    pub fn add_receipt_nt(
+        &self,
+        rct: &SignedNontransferableReceipt,
+    ) -> Result<(), error::KeriError> {
+        let receipted = &rct.body.event;
+        let name = Self::receipts_ref(
+            refs::NT_RECEIPTS,
+            receipted.sn,
+            &receipted.receipted_event_digest,
+        );
+        for couplet in &rct.couplets {
+            let single = SignedNontransferableReceipt::new(&rct.body, vec![couplet.clone()]);
+            self.storage.insert_entry(
+                &receipted.prefix,
+                &name,
+                &couplet.0.to_str(),
+                SignedEventData::from(single).to_cesr()?.as_slice(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Store a transferable (validator) receipt
+    ///
+    /// The receipt is kept under `receipts/t/<sn>/<digest>` of the receipted
+    /// identifier, in an entry named after the validator. Signatures made by
+    /// the validator with the same establishment event are merged.
+    pub fn add_receipt_t(&self, rct: &SignedTransferableReceipt) -> Result<(), error::KeriError> {
+        let receipted = &rct.body.event;
+        let name = Self::receipts_ref(
+            refs::T_RECEIPTS,
+            receipted.sn,
+            &receipted.receipted_event_digest,
+        );
+        let validator = rct.validator_seal.prefix.to_str();
+
+        let mut rct = rct.clone();
+        if let Some(known) = self.t_receipt(&receipted.prefix, &name, &validator)? {
+            if known.validator_seal == rct.validator_seal {
+                for sig in known.signatures {
+                    if !rct.signatures.iter().any(|s| s.index == sig.index) {
+                        rct.signatures.push(sig);
+                    }
+                }
+            }
+        }
+        self.storage.insert_entry(
+            &receipted.prefix,
+            &name,
+            &validator,
+            SignedEventData::from(rct).to_cesr()?.as_slice(),
+        )?;
+        Ok(())
+    }
+
+    /// Nontransferable receipts for the event at `sn` with digest `dig`
+    pub fn nt_receipts(
+        &self,
+        pref: &IdentifierPrefix,
+        sn: u64,
+        dig: &SelfAddressingPrefix,
+    ) -> Result<Vec<SignedNontransferableReceipt>, error::KeriError> {
+        let name = Self::receipts_ref(refs::NT_RECEIPTS, sn, dig);
+        self.storage
+            .entries(pref, &name)?
+            .into_iter()
+            .map(|(entry, raw)| match Self::parse_message(&raw) {
+                Some(Message::NontransferableRct(rct)) => Ok(rct),
+                _ => Err(error::KeriError::NotAReceipt(format!("{}/{}", name, entry))),
+            })
+            .collect()
+    }
+
+    /// Transferable receipts for the event at `sn` with digest `dig`
+    pub fn t_receipts(
+        &self,
+        pref: &IdentifierPrefix,
+        sn: u64,
+        dig: &SelfAddressingPrefix,
+    ) -> Result<Vec<SignedTransferableReceipt>, error::KeriError> {
+        let name = Self::receipts_ref(refs::T_RECEIPTS, sn, dig);
+        self.storage
+            .entries(pref, &name)?
+            .into_iter()
+            .map(|(entry, raw)| match Self::parse_message(&raw) {
+                Some(Message::TransferableRct(rct)) => Ok(*rct),
+                _ => Err(error::KeriError::NotAReceipt(format!("{}/{}", name, entry))),
+            })
+            .collect()
+    }
+
+    /// Escrowed nontransferable receipts for the event with digest `dig`
+    pub fn escrowed_nt_receipts(
+        &self,
+        pref: &IdentifierPrefix,
+        dig: &SelfAddressingPrefix,
+    ) -> Result<Vec<SignedNontransferableReceipt>, error::KeriError> {
+        Ok(self
+            .escrowed_nt(pref)?
+            .into_iter()
+            .filter(|rct| &rct.body.event.receipted_event_digest == dig)
+            .collect())
+    }
+
+    /// Escrowed transferable receipts for the event with digest `dig`
+    pub fn escrowed_t_receipts(
+        &self,
+        pref: &IdentifierPrefix,
+        dig: &SelfAddressingPrefix,
+    ) -> Result<Vec<SignedTransferableReceipt>, error::KeriError> {
+        Ok(self
+            .escrowed_t(pref)?
+            .into_iter()
+            .filter(|rct| &rct.body.event.receipted_event_digest == dig)
+            .collect())
+    }
+
+    fn report_duplicity(
+        &self,
+        processor: &EventProcessor,
+        local: &SignedEventMessage,
+        remote: &SignedEventMessage,
+    ) -> Result<DuplicityReport, error::KeriError> {
+        let prefix = remote.event_message.event.get_prefix();
+        let sn = remote.event_message.event.get_sn();
+        let dig = remote.event_message.get_digest();
+
+        // The processor holds the local KEL, it tells a conflicting event
+        // signed by the controller from one that doesn't verify
+        let verified = match processor.process(Message::Event(Box::new(remote.clone()))) {
+            Ok(_) | Err(Error::DuplicitousEventError) => true,
+            Err(Error::SignatureVerificationError) | Err(Error::NotEnoughSigsError) => false,
+            Err(e) => return Err(e.into()),
+        };
+
+        self.log_event(
+            &prefix,
+            &dig,
+            &remote.event_message.serialize()?,
+            &remote.signatures,
+        )?;
+        if verified {
+            self.duplicitous_event(&prefix, sn, &dig)?;
+        } else {
+            self.likely_duplicitous_event(&prefix, sn, &dig)?;
+        }
+
+        Ok(DuplicityReport {
+            prefix,
+            sn,
+            local: local.event_message.get_digest(),
+            remote: dig,
+            verified,
+        })
+    }
+
+    fn receipts_ref(name: &str, sn: u64, dig: &SelfAddressingPrefix) -> String {
+        format!("{}/{}/{}", name, sn, dig.to_str())
+    }
+
+    fn t_receipt(
+        &self,
+        pref: &IdentifierPrefix,
+        name: &str,
+        validator: &str,
+    ) -> Result<Option<SignedTransferableReceipt>, error::KeriError> {
+        match self.storage.entry(pref, name, validator)? {
+            Some(raw) => match Self::parse_message(&raw) {
+                Some(Message::TransferableRct(rct)) => Ok(Some(*rct)),
+                _ => Err(error::KeriError::NotAReceipt(format!(
+                    "{}/{}",
+                    name, validator
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn parse_message(raw: &[u8]) -> Option<Message> {
+        signed_message(raw)
+            .ok()
+            .and_then(|(_, data)| Message::try_from(data).ok())
+    }
+
+    // The receipt body is rebuilt from the logged event, as the trait
+    // functions only carry its digest. Events appended to the KEL without
+    // being logged, e.g. through `KeriStore::append`, are looked up there.
+    fn receipt_body(
+        &self,
+        pref: &IdentifierPrefix,
+        dig: &SelfAddressingPrefix,
+    ) -> Result<EventMessage<Receipt>, error::KeriError> {
+        let event = match self.logged_event(pref, dig)? {
+            Some(event) => event,
+            None => self
+                .kel(pref)?
+                .into_iter()
+                .find(|event| &event.event_message.get_digest() == dig)
+                .ok_or_else(|| error::KeriError::UnknownEvent(dig.to_str()))?,
+        };
+        Ok(Receipt {
+            receipted_event_digest: dig.clone(),
+            prefix: pref.clone(),
+            sn: event.event_message.event.get_sn(),
+        }
+        .to_message(SerializationFormats::JSON)?)
+    }
+
+    fn mark_event(
+        &self,
+        pref: &IdentifierPrefix,
+        name: &str,
+        sn: u64,
+        dig: &SelfAddressingPrefix,
+    ) -> Result<(), error::KeriError> {
+        self.storage
+            .insert_entry(pref, name, &dig.to_str(), sn.to_string().as_bytes())?;
+        Ok(())
+    }
+
+    fn marked_events(
+        &self,
+        pref: &IdentifierPrefix,
+        name: &str,
+    ) -> Result<Vec<(SelfAddressingPrefix, u64)>, error::KeriError> {
+        self.storage
+            .entries(pref, name)?
+            .into_iter()
+            .map(|(dig, sn)| {
+                let sn = std::str::from_utf8(&sn)
+                    .ok()
+                    .and_then(|sn| sn.parse().ok())
+                    .ok_or_else(|| error::KeriError::NotAnEvent(dig.clone()))?;
+                Ok((dig.parse()?, sn))
+            })
+            .collect()
+    }
+
+    /// Log `event` and mark it in the KERI ref `name`
+    fn add_marked_event(
+        &self,
+        pref: &IdentifierPrefix,
+        name: &str,
+        event: &SignedEventMessage,
+    ) -> Result<(), error::KeriError> {
+        let dig = event.event_message.get_digest();
+        self.log_event(
+            pref,
+            &dig,
+            &event.event_message.serialize()?,
+            &event.signatures,
+        )?;
+        self.mark_event(pref, name, event.event_message.event.get_sn(), &dig)
+    }
+
+    /// Logged events marked in the KERI ref `name`
+    fn marked_logged_events(
+        &self,
+        pref: &IdentifierPrefix,
+        name: &str,
+    ) -> Result<Vec<SignedEventMessage>, error::KeriError> {
+        self.marked_events(pref, name)?
+            .into_iter()
+            .map(|(dig, _)| {
+                self.logged_event(pref, &dig)?
+                    .ok_or_else(|| error::KeriError::UnknownEvent(dig.to_str()))
+            })
+            .collect()
+    }
+
+    fn unmark_event(
+        &self,
+        pref: &IdentifierPrefix,
+        name: &str,
+        dig: &SelfAddressingPrefix,
+    ) -> Result<(), error::KeriError> {
+        self.storage.remove_entry(pref, name, &dig.to_str())?;
+        Ok(())
+    }
+
+    // Messages kept as a set are stored under the digest of their
+    // encoding, so storing one twice keeps a single copy.
+    fn add_message(
+        &self,
+        pref: &IdentifierPrefix,
+        name: &str,
+        message: SignedEventData,
+    ) -> Result<(), error::KeriError> {
+        let raw = message.to_cesr()?;
+        let entry = SelfAddressing::Blake3_256.derive(&raw).to_str();
+        self.storage.insert_entry(pref, name, &entry, &raw)?;
+        Ok(())
+    }
+
+    fn remove_message(
+        &self,
+        pref: &IdentifierPrefix,
+        name: &str,
+        message: SignedEventData,
+    ) -> Result<(), error::KeriError> {
+        let raw = message.to_cesr()?;
+        let entry = SelfAddressing::Blake3_256.derive(&raw).to_str();
+        self.storage.remove_entry(pref, name, &entry)?;
+        Ok(())
+    }
+
+    fn messages(
+        &self,
+        pref: &IdentifierPrefix,
+        name: &str,
+    ) -> Result<Vec<Message>, error::KeriError> {
+        self.storage
+            .entries(pref, name)?
+            .into_iter()
+            .map(|(entry, raw)| {
+                Self::parse_message(&raw)
+                    .ok_or_else(|| error::KeriError::NotAMessage(format!("{}/{}", name, entry)))
+            })
+            .collect()
+    }
+
+    fn escrowed_nt(
+        &self,
+        pref: &IdentifierPrefix,
+    ) -> Result<Vec<SignedNontransferableReceipt>, error::KeriError> {
+        self.messages(pref, refs::ESCROWED_NT_RECEIPTS)?
+            .into_iter()
+            .map(|message| match message {
+                Message::NontransferableRct(rct) => Ok(rct),
+                _ => Err(error::KeriError::NotAReceipt(
+                    refs::ESCROWED_NT_RECEIPTS.to_string(),
+                )),
+            })
+            .collect()
+    }
+
+    fn escrowed_t(
+        &self,
+        pref: &IdentifierPrefix,
+    ) -> Result<Vec<SignedTransferableReceipt>, error::KeriError> {
+        self.messages(pref, refs::ESCROWED_T_RECEIPTS)?
+            .into_iter()
+            .map(|message| match message {
+                Message::TransferableRct(rct) => Ok(*rct),
+                _ => Err(error::KeriError::NotAReceipt(
+                    refs::ESCROWED_T_RECEIPTS.to_string(),
+                )),
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "query")]
+    fn replies(
+        &self,
+        pref: &IdentifierPrefix,
+        name: &str,
+    ) -> Result<Vec<SignedReply>, error::KeriError> {
+        self.messages(pref, name)?
+            .into_iter()
+            .map(|message| match message {
+                Message::KeyStateNotice(rpy) => Ok(rpy),
+                _ => Err(error::KeriError::NotAMessage(name.to_string())),
+            })
+            .collect()
+    }
+
+    /// Receipts stored for each event of the KEL of `pref`
+    fn kel_receipts<T>(
+        &self,
+        pref: &IdentifierPrefix,
+        receipts: impl Fn(u64, &SelfAddressingPrefix) -> Result<Vec<T>, error::KeriError>,
+    ) -> Result<Option<Vec<T>>, error::KeriError> {
+        let kel = self.kel(pref)?;
+        if kel.is_empty() {
+            return Ok(None);
+        }
+        let mut all = vec![];
+        for event in kel {
+            all.append(&mut receipts(
+                event.event_message.event.get_sn(),
+                &event.event_message.get_digest(),
+            )?);
+        }
+        Ok(Some(all))
+    }
+
+    /// Add `event` to the KEL, the first event of a delegated identifier
+    /// starting a project KEL
+    fn append_event(&self, event: &SignedEventMessage) -> Result<(), error::KeriError> {
+        match event.event_message.event.get_event_data() {
+            // Delegated identifiers are projects
+            EventData::Dip(_) => self.storage.append_project(event)?,
+            _ => self.storage.append(event)?,
+        };
+        Ok(())
+    }
+}
+
+/// Values returned by `EventStorage` getters, `None` when there are none
+fn stored<T>(values: Vec<T>) -> Option<DbIter<T>> {
+    (!values.is_empty()).then(|| values.into_iter())
 }
 
 impl<'k> EventDatabase for GitStorageDatabase<'k> {
-    type Error = crate::keri_store::error::KeriError;
+    type Error = error::KeriError;
 
     fn last_event_at_sn(
         &self,
-        pref: &IdentifierPrefix, // TODO: map identifier to namespace
+        pref: &IdentifierPrefix,
         sn: u64,
     ) -> Result<Option<Vec<u8>>, Self::Error> {
-        let event = self.storage.log_entry_sn(sn).unwrap();
-
-        match event {
-            Some(e) => {
-                let mut event_bytes = Vec::new();
-                ciborium::ser::into_writer(&e, &mut event_bytes).unwrap();
-                Ok(Some(event_bytes))
-            }
+        match self.storage.log_entry_sn(pref, sn)? {
+            Some(e) => Ok(Some(e.signed_event_message.event_message.serialize()?)),
             None => Ok(None),
         }
     }
 
     fn get_kerl(&self, id: &IdentifierPrefix) -> Result<Option<Vec<u8>>, Self::Error> {
-        let mut kerl = self.storage.log_entries(None)?;
-
-        let kerl_bytes = Vec::<u8>::new();
+        let mut kerl = match self.storage.log_entries(id, None) {
+            Ok(kerl) => kerl,
+            Err(keri_store::error::KeriError::FindRef(keri_store::error::FindEntry::Empty)) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        };
 
         if kerl.is_empty() {
             Ok(None)
         } else {
             kerl.reverse();
-            let kerl_bytes: Vec<u8> = kerl.into_iter().fold(kerl_bytes, |mut kb, e| {
-                let mut event_bytes = Vec::new();
-                ciborium::ser::into_writer(&e, &mut event_bytes).unwrap();
-                kb.append(&mut event_bytes);
-                kb
-            });
+            let mut kerl_bytes = Vec::<u8>::new();
+            for e in kerl {
+                kerl_bytes.extend(e.signed_event_message.serialize()?);
+            }
             Ok(Some(kerl_bytes))
         }
     }
@@ -115,7 +784,17 @@ impl<'k> EventDatabase for GitStorageDatabase<'k> {
         raw: &[u8],
         sigs: &[AttachedSignaturePrefix],
     ) -> Result<(), Self::Error> {
-        todo!()
+        let signed = [
+            raw,
+            Attachment::AttachedSignatures(sigs.to_vec())
+                .to_cesr()
+                .as_bytes(),
+        ]
+        .concat();
+        let name = format!("{}/{}", refs::EVENTS, dig.to_str());
+        self.storage
+            .insert_entry(prefix, &name, KERI_EVENT_ENTRY, &signed)?;
+        Ok(())
     }
 
     fn finalise_event(
@@ -124,7 +803,10 @@ impl<'k> EventDatabase for GitStorageDatabase<'k> {
         sn: u64,
         dig: &SelfAddressingPrefix,
     ) -> Result<(), Self::Error> {
-        todo!()
+        let event = self
+            .logged_event(prefix, dig)?
+            .ok_or_else(|| error::KeriError::UnknownEvent(dig.to_str()))?;
+        self.append_event(&event)
     }
 
     fn escrow_partially_signed_event(
@@ -133,7 +815,7 @@ impl<'k> EventDatabase for GitStorageDatabase<'k> {
         sn: u64,
         dig: &SelfAddressingPrefix,
     ) -> Result<(), Self::Error> {
-        todo!()
+        self.mark_event(pref, refs::PARTIALLY_SIGNED, sn, dig)
     }
 
     fn escrow_out_of_order_event(
@@ -142,7 +824,7 @@ impl<'k> EventDatabase for GitStorageDatabase<'k> {
         sn: u64,
         dig: &SelfAddressingPrefix,
     ) -> Result<(), Self::Error> {
-        todo!()
+        self.mark_event(pref, refs::OUT_OF_ORDER, sn, dig)
     }
 
     fn likely_duplicitous_event(
@@ -151,7 +833,7 @@ impl<'k> EventDatabase for GitStorageDatabase<'k> {
         sn: u64,
         dig: &SelfAddressingPrefix,
     ) -> Result<(), Self::Error> {
-        todo!()
+        self.mark_event(pref, refs::LIKELY_DUPLICITOUS, sn, dig)
     }
 
     fn duplicitous_event(
@@ -160,7 +842,7 @@ impl<'k> EventDatabase for GitStorageDatabase<'k> {
         sn: u64,
         dig: &SelfAddressingPrefix,
     ) -> Result<(), Self::Error> {
-        todo!()
+        self.mark_event(pref, refs::DUPLICITOUS, sn, dig)
     }
 
     fn add_nt_receipt_for_event(
@@ -170,17 +852,26 @@ impl<'k> EventDatabase for GitStorageDatabase<'k> {
         signer: &BasicPrefix,
         sig: &SelfSigningPrefix,
     ) -> Result<(), Self::Error> {
-        todo!()
+        let body = self.receipt_body(pref, dig)?;
+        self.add_receipt_nt(&SignedNontransferableReceipt::new(
+            &body,
+            vec![(signer.clone(), sig.clone())],
+        ))
     }
 
     fn add_t_receipt_for_event(
         &self,
         pref: &IdentifierPrefix,
         dig: &SelfAddressingPrefix,
-        signer: &IdentifierPrefix,
+        validator_seal: &EventSeal,
         sig: &AttachedSignaturePrefix,
     ) -> Result<(), Self::Error> {
-        todo!()
+        let body = self.receipt_body(pref, dig)?;
+        self.add_receipt_t(&SignedTransferableReceipt::new(
+            body,
+            validator_seal.clone(),
+            vec![sig.clone()],
+        ))
     }
 
     fn escrow_nt_receipt(
@@ -190,17 +881,21 @@ impl<'k> EventDatabase for GitStorageDatabase<'k> {
         signer: &BasicPrefix,
         sig: &SelfSigningPrefix,
     ) -> Result<(), Self::Error> {
-        todo!()
+        let body = self.receipt_body(pref, dig)?;
+        let rct = SignedNontransferableReceipt::new(&body, vec![(signer.clone(), sig.clone())]);
+        self.add_message(pref, refs::ESCROWED_NT_RECEIPTS, rct.into())
     }
 
     fn escrow_t_receipt(
         &self,
         pref: &IdentifierPrefix,
         dig: &SelfAddressingPrefix,
-        signer: &IdentifierPrefix,
+        validator_seal: &EventSeal,
         sig: &AttachedSignaturePrefix,
     ) -> Result<(), Self::Error> {
-        todo!()
+        let body = self.receipt_body(pref, dig)?;
+        let rct = SignedTransferableReceipt::new(body, validator_seal.clone(), vec![sig.clone()]);
+        self.add_message(pref, refs::ESCROWED_T_RECEIPTS, rct.into())
     }
 
     fn has_receipt(
@@ -209,6 +904,632 @@ impl<'k> EventDatabase for GitStorageDatabase<'k> {
         sn: u64,
         validator: &IdentifierPrefix,
     ) -> Result<bool, Self::Error> {
-        todo!()
+        let dig = match self.storage.log_entry_sn(pref, sn)? {
+            Some(e) => e.signed_event_message.event_message.get_digest(),
+            None => return Ok(false),
+        };
+
+        Ok(self
+            .t_receipts(pref, sn, &dig)?
+            .iter()
+            .any(|rct| &rct.validator_seal.prefix == validator)
+            || self.nt_receipts(pref, sn, &dig)?.iter().any(|rct| {
+                rct.couplets
+                    .iter()
+                    .any(|(signer, _)| &IdentifierPrefix::Basic(signer.clone()) == validator)
+            }))
+    }
+}
+
+/// Lets an `EventProcessor` run over git storage
+///
+/// Finalized events make up the KEL commit chain, escrowed, duplicitous and
+/// likely duplicitous events are logged and marked like through
+/// `EventDatabase`, and receipts are stored next to the event they receipt
+/// once it's accepted. Key states are not cached, the processor replays the
+/// KEL instead. Reading or writing git fails with
+/// `keri::error::Error::BackendError`.
+impl<'k> EventStorage for GitStorageDatabase<'k> {
+    fn add_kel_finalized_event(
+        &self,
+        event: SignedEventMessage,
+        _id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Ok(self.append_event(&event)?)
+    }
+
+    fn get_kel_finalized_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        let kel = self.kel(id)?;
+        Ok(stored(kel.into_iter().map(Into::into).collect()))
+    }
+
+    /// The events after `event` are removed as well, a KEL has no gaps
+    fn remove_kel_finalized_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        let sn = event.event_message.event.get_sn();
+        match self.storage.log_entry_sn(id, sn) {
+            Ok(Some(logged)) if logged.signed_event_message == *event => self
+                .storage
+                .rewind(id, sn)
+                .map_err(|e| error::KeriError::from(e).into()),
+            Ok(_)
+            | Err(keri_store::error::KeriError::FindRef(keri_store::error::FindEntry::Empty)) => {
+                Ok(())
+            }
+            Err(e) => Err(error::KeriError::from(e).into()),
+        }
+    }
+
+    /// The KEL moves with a single ref update, superseded events included,
+    /// and only if its head is still the one the event was validated
+    /// against, checked by the update itself. The other records live in
+    /// refs of their own and follow it one by one, so they are not atomic
+    /// with the KEL. Key states are not cached.
+    fn accept_event(&self, acceptance: EventAcceptance) -> Result<(), Error> {
+        use keri_store::error::Append;
+
+        let id = &acceptance.event.event_message.event.get_prefix();
+        let appended = if acceptance.superseded.is_empty() {
+            match acceptance.event.event_message.event.get_event_data() {
+                EventData::Dip(_) => self.storage.append_project(&acceptance.event),
+                _ => self.storage.append(&acceptance.event),
+            }
+        } else {
+            self.storage
+                .supersede(&acceptance.event, &acceptance.superseded)
+        };
+        match appended {
+            Ok(_) => (),
+            // another processor changed the KEL since the event was validated
+            Err(
+                Append::Conflict
+                | Append::NotInception
+                | Append::OutOfOrder { .. }
+                | Append::PriorDigestMismatch,
+            ) => return Err(Error::KelChangedError),
+            Err(e) => return Err(error::KeriError::from(e).into()),
+        }
+        for event in acceptance.duplicitous {
+            self.add_duplicious_event(event, id)?;
+        }
+        for rct in &acceptance.escrowed_receipts_nt {
+            self.remove_escrow_nt_receipt(id, rct)?;
+        }
+        for rct in &acceptance.receipts_nt {
+            GitStorageDatabase::add_receipt_nt(self, rct)?;
+        }
+        for vrc in &acceptance.escrowed_receipts_t {
+            self.remove_escrow_t_receipt(id, vrc)?;
+        }
+        for vrc in &acceptance.receipts_t {
+            GitStorageDatabase::add_receipt_t(self, vrc)?;
+        }
+        Ok(())
+    }
+
+    fn get_key_state(&self, _id: &IdentifierPrefix) -> Result<Option<IdentifierState>, Error> {
+        Ok(None)
+    }
+
+    fn update_key_state(
+        &self,
+        _id: &IdentifierPrefix,
+        _state: &IdentifierState,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn remove_key_state(&self, _id: &IdentifierPrefix) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn add_receipt_t(
+        &self,
+        receipt: SignedTransferableReceipt,
+        _id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Ok(GitStorageDatabase::add_receipt_t(self, &receipt)?)
+    }
+
+    fn get_receipts_t(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedTransferableReceipt>>, Error> {
+        let receipts = self.kel_receipts(id, |sn, dig| self.t_receipts(id, sn, dig))?;
+        Ok(receipts.map(Vec::into_iter))
+    }
+
+    fn add_receipt_nt(
+        &self,
+        receipt: SignedNontransferableReceipt,
+        _id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Ok(GitStorageDatabase::add_receipt_nt(self, &receipt)?)
+    }
+
+    fn get_receipts_nt(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedNontransferableReceipt>>, Error> {
+        let receipts = self.kel_receipts(id, |sn, dig| self.nt_receipts(id, sn, dig))?;
+        Ok(receipts.map(Vec::into_iter))
+    }
+
+    fn remove_receipts_nt(&self, id: &IdentifierPrefix) -> Result<(), Error> {
+        for event in self.kel(id)? {
+            let name = Self::receipts_ref(
+                refs::NT_RECEIPTS,
+                event.event_message.event.get_sn(),
+                &event.event_message.get_digest(),
+            );
+            for (entry, _) in self
+                .storage
+                .entries(id, &name)
+                .map_err(error::KeriError::from)?
+            {
+                self.storage
+                    .remove_entry(id, &name, &entry)
+                    .map_err(error::KeriError::from)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_escrow_t_receipt(
+        &self,
+        receipt: SignedTransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Ok(self.add_message(id, refs::ESCROWED_T_RECEIPTS, receipt.into())?)
+    }
+
+    fn get_escrow_t_receipts(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedTransferableReceipt>>, Error> {
+        Ok(stored(self.escrowed_t(id)?))
+    }
+
+    fn remove_escrow_t_receipt(
+        &self,
+        id: &IdentifierPrefix,
+        receipt: &SignedTransferableReceipt,
+    ) -> Result<(), Error> {
+        Ok(self.remove_message(id, refs::ESCROWED_T_RECEIPTS, receipt.clone().into())?)
+    }
+
+    fn add_escrow_nt_receipt(
+        &self,
+        receipt: SignedNontransferableReceipt,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Ok(self.add_message(id, refs::ESCROWED_NT_RECEIPTS, receipt.into())?)
+    }
+
+    fn get_escrow_nt_receipts(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedNontransferableReceipt>>, Error> {
+        Ok(stored(self.escrowed_nt(id)?))
+    }
+
+    fn remove_escrow_nt_receipt(
+        &self,
+        id: &IdentifierPrefix,
+        receipt: &SignedNontransferableReceipt,
+    ) -> Result<(), Error> {
+        Ok(self.remove_message(id, refs::ESCROWED_NT_RECEIPTS, receipt.clone().into())?)
+    }
+
+    fn add_likely_duplicious_event(
+        &self,
+        event: EventMessage<KeyEvent>,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        let event = SignedEventMessage::new(&event, vec![], None);
+        Ok(self.add_marked_event(id, refs::LIKELY_DUPLICITOUS, &event)?)
+    }
+
+    fn get_likely_duplicitous_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedEventMessage>>, Error> {
+        let events = self.marked_logged_events(id, refs::LIKELY_DUPLICITOUS)?;
+        Ok(stored(
+            events
+                .into_iter()
+                .map(|event| TimestampedEventMessage::new(event.event_message))
+                .collect(),
+        ))
+    }
+
+    fn add_duplicious_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Ok(self.add_marked_event(id, refs::DUPLICITOUS, &event)?)
+    }
+
+    fn get_duplicious_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        let events = self.marked_logged_events(id, refs::DUPLICITOUS)?;
+        Ok(stored(events.into_iter().map(Into::into).collect()))
+    }
+
+    fn add_out_of_order_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Ok(self.add_marked_event(id, refs::OUT_OF_ORDER, &event)?)
+    }
+
+    fn get_out_of_order_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        let events = self.marked_logged_events(id, refs::OUT_OF_ORDER)?;
+        Ok(stored(events.into_iter().map(Into::into).collect()))
+    }
+
+    fn remove_out_of_order_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        let dig = event.event_message.get_digest();
+        Ok(self.unmark_event(id, refs::OUT_OF_ORDER, &dig)?)
+    }
+
+    fn add_partially_signed_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Ok(self.add_marked_event(id, refs::PARTIALLY_SIGNED, &event)?)
+    }
+
+    fn get_partially_signed_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        let events = self.marked_logged_events(id, refs::PARTIALLY_SIGNED)?;
+        Ok(stored(events.into_iter().map(Into::into).collect()))
+    }
+
+    fn remove_partially_signed_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        let dig = event.event_message.get_digest();
+        Ok(self.unmark_event(id, refs::PARTIALLY_SIGNED, &dig)?)
+    }
+
+    fn add_partially_witnessed_event(
+        &self,
+        event: SignedEventMessage,
+        id: &IdentifierPrefix,
+    ) -> Result<(), Error> {
+        Ok(self.add_marked_event(id, refs::PARTIALLY_WITNESSED, &event)?)
+    }
+
+    fn get_partially_witnessed_events(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<TimestampedSignedEventMessage>>, Error> {
+        let events = self.marked_logged_events(id, refs::PARTIALLY_WITNESSED)?;
+        Ok(stored(events.into_iter().map(Into::into).collect()))
+    }
+
+    fn remove_partially_witnessed_event(
+        &self,
+        id: &IdentifierPrefix,
+        event: &SignedEventMessage,
+    ) -> Result<(), Error> {
+        let dig = event.event_message.get_digest();
+        Ok(self.unmark_event(id, refs::PARTIALLY_WITNESSED, &dig)?)
+    }
+
+    #[cfg(feature = "query")]
+    fn update_accepted_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error> {
+        let route = rpy.reply.event.get_route();
+        for accepted in self.replies(id, refs::ACCEPTED_REPLIES)? {
+            if accepted.reply.event.get_route() == route {
+                self.remove_message(id, refs::ACCEPTED_REPLIES, accepted.into())?;
+            }
+        }
+        Ok(self.add_message(id, refs::ACCEPTED_REPLIES, rpy.into())?)
+    }
+
+    #[cfg(feature = "query")]
+    fn get_accepted_replys(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedReply>>, Error> {
+        Ok(stored(self.replies(id, refs::ACCEPTED_REPLIES)?))
+    }
+
+    #[cfg(feature = "query")]
+    fn remove_accepted_reply(&self, id: &IdentifierPrefix, rpy: SignedReply) -> Result<(), Error> {
+        Ok(self.remove_message(id, refs::ACCEPTED_REPLIES, rpy.into())?)
+    }
+
+    #[cfg(feature = "query")]
+    fn add_escrowed_reply(&self, rpy: SignedReply, id: &IdentifierPrefix) -> Result<(), Error> {
+        Ok(self.add_message(id, refs::ESCROWED_REPLIES, rpy.into())?)
+    }
+
+    #[cfg(feature = "query")]
+    fn get_escrowed_replys(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<Option<DbIter<SignedReply>>, Error> {
+        Ok(stored(self.replies(id, refs::ESCROWED_REPLIES)?))
+    }
+
+    #[cfg(feature = "query")]
+    fn remove_escrowed_reply(&self, id: &IdentifierPrefix, rpy: SignedReply) -> Result<(), Error> {
+        Ok(self.remove_message(id, refs::ESCROWED_REPLIES, rpy.into())?)
+    }
+
+    #[cfg(feature = "query")]
+    fn get_all_escrowed_replys(&self) -> Result<Option<DbIter<SignedReply>>, Error> {
+        let mut all = vec![];
+        let ids = self
+            .storage
+            .identifiers_with(refs::ESCROWED_REPLIES)
+            .map_err(error::KeriError::from)?;
+        for id in ids {
+            all.append(&mut self.replies(&id, refs::ESCROWED_REPLIES)?);
+        }
+        Ok(stored(all))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use keri::database::sled::SledEventDatabase;
+    use keri::derivation::{basic::Basic, self_signing::SelfSigning};
+    use keri::signer::{CryptoBox, KeyManager};
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::test_utils::{commit, incept, interact, storage, Result};
+
+    fn receipt_body(event: &SignedEventMessage) -> Result<EventMessage<Receipt>> {
+        Ok(Receipt {
+            prefix: event.event_message.event.get_prefix(),
+            sn: event.event_message.event.get_sn(),
+            receipted_event_digest: event.event_message.get_digest(),
+        }
+        .to_message(SerializationFormats::JSON)?)
+    }
+
+    fn processor(root: &Path) -> Result<EventProcessor> {
+        let db = SledEventDatabase::new(root.join("db").as_path())?;
+        Ok(EventProcessor::new(Arc::new(db)))
+    }
+
+    /// Commit holding `event` on top of `parent`, as fetched from a remote
+    fn fetched(storage: &Write, event: &SignedEventMessage, parent: &Commit) -> Result<Commit> {
+        let raw = SignedEventData::from(event).to_cesr()?;
+        commit(storage, &[(KERI_EVENT_ENTRY, &raw)], &[parent])
+    }
+
+    #[test]
+    fn test_reconcile_fast_forward() -> Result<()> {
+        let root = Builder::new().prefix("test-fast-forward").tempdir()?;
+        let storage = storage(root.path())?;
+        let db = GitStorageDatabase::open(&storage)?;
+        let processor = processor(root.path())?;
+
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let ixn = interact(&km, &icp, b"first")?;
+        let next_ixn = interact(&km, &ixn, b"second")?;
+        let id = icp.event_message.event.get_prefix();
+        db.storage.append(&icp)?;
+
+        let remote = fetched(&storage, &ixn, &db.storage.log_head(&id)?)?;
+        let remote = fetched(&storage, &next_ixn, &remote)?;
+        assert_eq!(
+            db.reconcile(&processor, &id, &remote)?,
+            Reconciliation::FastForward { sn: 2 }
+        );
+        assert_eq!(db.storage.log_head(&id)?.id(), remote.id());
+        assert_eq!(db.kel(&id)?, vec![icp, ixn, next_ixn]);
+        assert!(processor.get_event_at_sn(&id, 2)?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconcile_local_prefix() -> Result<()> {
+        let root = Builder::new().prefix("test-local-prefix").tempdir()?;
+        let storage = storage(root.path())?;
+        let db = GitStorageDatabase::open(&storage)?;
+        let processor = processor(root.path())?;
+
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let ixn = interact(&km, &icp, b"first")?;
+        let next_ixn = interact(&km, &ixn, b"second")?;
+        let id = icp.event_message.event.get_prefix();
+        for event in [&icp, &ixn, &next_ixn] {
+            db.storage.append(event)?;
+        }
+        let head = db.storage.log_head(&id)?;
+
+        // The remote is behind
+        let remote = head.parents().next().ok_or("no parent")?;
+        assert_eq!(
+            db.reconcile(&processor, &id, &remote)?,
+            Reconciliation::UpToDate
+        );
+        assert_eq!(db.storage.log_head(&id)?.id(), head.id());
+        assert_eq!(db.kel(&id)?, vec![icp, ixn, next_ixn]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconcile_fork() -> Result<()> {
+        let root = Builder::new().prefix("test-fork").tempdir()?;
+        let storage = storage(root.path())?;
+        let db = GitStorageDatabase::open(&storage)?;
+        let processor = processor(root.path())?;
+
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let ixn = interact(&km, &icp, b"first")?;
+        let id = icp.event_message.event.get_prefix();
+        db.storage.append(&icp)?;
+        db.storage.append(&ixn)?;
+        let icp_commit = db
+            .storage
+            .log_head(&id)?
+            .parents()
+            .next()
+            .ok_or("no parent")?;
+
+        // Signed by the controller: duplicitous
+        let fork = interact(&km, &icp, b"fork")?;
+        let remote = fetched(&storage, &fork, &icp_commit)?;
+        assert_eq!(
+            db.reconcile(&processor, &id, &remote)?,
+            Reconciliation::Duplicity(DuplicityReport {
+                prefix: id.clone(),
+                sn: 1,
+                local: ixn.event_message.get_digest(),
+                remote: fork.event_message.get_digest(),
+                verified: true,
+            })
+        );
+        assert_eq!(
+            db.duplicitous_events(&id)?,
+            vec![(fork.event_message.get_digest(), 1)]
+        );
+
+        // Signed by anyone else: likely duplicitous
+        let forged = interact(&CryptoBox::new()?, &icp, b"forged")?;
+        let remote = fetched(&storage, &forged, &icp_commit)?;
+        assert_eq!(
+            db.reconcile(&processor, &id, &remote)?,
+            Reconciliation::Duplicity(DuplicityReport {
+                prefix: id.clone(),
+                sn: 1,
+                local: ixn.event_message.get_digest(),
+                remote: forged.event_message.get_digest(),
+                verified: false,
+            })
+        );
+        assert_eq!(
+            db.likely_duplicitous_events(&id)?,
+            vec![(forged.event_message.get_digest(), 1)]
+        );
+        assert_eq!(db.kel(&id)?, vec![icp, ixn]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receipts() -> Result<()> {
+        let root = Builder::new().prefix("test-receipts").tempdir()?;
+        let storage = storage(root.path())?;
+        let db = GitStorageDatabase::open(&storage)?;
+
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let id = icp.event_message.event.get_prefix();
+        let dig = icp.event_message.get_digest();
+        db.storage.append(&icp)?;
+        let serialized = icp.event_message.serialize()?;
+
+        let witness_km = CryptoBox::new()?;
+        let witness = Basic::Ed25519.derive(witness_km.public_key()?);
+        let nt = SignedNontransferableReceipt::new(
+            &receipt_body(&icp)?,
+            vec![(
+                witness.clone(),
+                SelfSigning::Ed25519Sha512.derive(witness_km.sign(&serialized)?),
+            )],
+        );
+        db.add_receipt_nt(&nt)?;
+
+        let validator_km = CryptoBox::new()?;
+        let validator_icp = incept(&validator_km)?;
+        let validator = validator_icp.event_message.event.get_prefix();
+        let seal = EventSeal {
+            prefix: validator.clone(),
+            sn: 0,
+            event_digest: validator_icp.event_message.get_digest(),
+        };
+        let signature = |index| -> Result<AttachedSignaturePrefix> {
+            Ok(AttachedSignaturePrefix::new(
+                SelfSigning::Ed25519Sha512,
+                validator_km.sign(&serialized)?,
+                index,
+            ))
+        };
+        let t =
+            SignedTransferableReceipt::new(receipt_body(&icp)?, seal.clone(), vec![signature(0)?]);
+        db.add_receipt_t(&t)?;
+
+        assert_eq!(db.nt_receipts(&id, 0, &dig)?, vec![nt]);
+        assert_eq!(db.t_receipts(&id, 0, &dig)?, vec![t]);
+        assert!(db.has_receipt(&id, 0, &IdentifierPrefix::Basic(witness))?);
+        assert!(db.has_receipt(&id, 0, &validator)?);
+        assert!(!db.has_receipt(&id, 0, &id)?);
+        assert!(!db.has_receipt(&id, 1, &validator)?);
+
+        // Signatures of the same validator establishment event are merged
+        let more = SignedTransferableReceipt::new(receipt_body(&icp)?, seal, vec![signature(1)?]);
+        db.add_receipt_t(&more)?;
+        let merged = db.t_receipts(&id, 0, &dig)?;
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].signatures.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receipt_for_appended_event() -> Result<()> {
+        let root = Builder::new().prefix("test-appended").tempdir()?;
+        let storage = storage(root.path())?;
+        let db = GitStorageDatabase::open(&storage)?;
+
+        // Appended to the KEL only, without a logged copy
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let id = icp.event_message.event.get_prefix();
+        let dig = icp.event_message.get_digest();
+        db.storage.append(&icp)?;
+        assert!(db.logged_event(&id, &dig)?.is_none());
+
+        let witness_km = CryptoBox::new()?;
+        let witness = Basic::Ed25519.derive(witness_km.public_key()?);
+        let sig =
+            SelfSigning::Ed25519Sha512.derive(witness_km.sign(&icp.event_message.serialize()?)?);
+        db.add_nt_receipt_for_event(&id, &dig, &witness, &sig)?;
+        db.escrow_nt_receipt(&id, &dig, &witness, &sig)?;
+
+        let expected =
+            SignedNontransferableReceipt::new(&receipt_body(&icp)?, vec![(witness, sig)]);
+        assert_eq!(db.nt_receipts(&id, 0, &dig)?, vec![expected.clone()]);
+        assert_eq!(db.escrowed_nt_receipts(&id, &dig)?, vec![expected]);
+
+        Ok(())
     }
 }
@@ -1,11 +1,32 @@
-use git_ref_format::{Qualified, RefStr};
-use git_storage::odb::Read as _;
-use git_storage::refdb::Read as _;
-use git_storage::{Commit, Write};
+use git_ref_format::{refspec::PatternString, Qualified, RefString};
+use git_storage::odb::{Read as _, TreeBuilder, Write as _};
+use git_storage::refdb::{Policy, PreviousValue, Read as _, Update, Write as _};
+use git_storage::{glob, Commit, Write};
 
-use git2::ObjectType;
+use git2::{FileMode, ObjectType};
 
-use keri::event_message::signed_event_message::TimestampedSignedEventMessage;
+use keri::event::event_data::EventData;
+use keri::event_message::signed_event_message::{
+    Message, SignedEventMessage, TimestampedSignedEventMessage,
+};
+use keri::event_parsing::{message::signed_message, SignedEventData};
+use keri::prefix::{IdentifierPrefix, Prefix};
+use keri::state::IdentifierState;
+
+/// Ref directory, inside the namespace of an identifier, holding everything
+/// KERI related
+pub const KERI_REFS: &str = "refs/keri";
+
+/// Name of the ref inside `KERI_REFS` under which the KEL commit chain is
+/// stored
+pub const KERI_LOG: &str = "kel";
+
+/// Ref, inside the namespace of a project identifier, under which the
+/// project KEL commit chain is stored
+pub const PROJECT_LOG_REF: &str = "refs/rad/kel";
+
+/// Name of the tree entry holding the event of a KEL commit
+pub const KERI_EVENT_ENTRY: &str = "event";
 
 pub mod error {
     use thiserror::Error;
@@ -37,20 +58,110 @@ pub mod error {
         FindCommit,
         #[error("No commit found in KEL")]
         Empty,
+        #[error(transparent)]
+        RefFormat(#[from] git_ref_format::Error),
+        #[error("Failed to list KERI refs")]
+        Iter,
+        #[error("Ref {0} is not inside an identifier namespace")]
+        Namespace(String),
+        #[error("Ref {name} is a symbolic ref to {target}, expected a commit")]
+        Symbolic { name: String, target: String },
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+    }
+
+    /// Integrity violations of a KEL commit chain
+    #[derive(Debug, Error)]
+    pub enum Integrity {
+        #[error(transparent)]
+        Git(#[from] git2::Error),
+        #[error(transparent)]
+        FindRef(#[from] FindEntry),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+        #[error("Commit {commit} holds {count} tree entries, expected a single event")]
+        EntryCount { commit: git2::Oid, count: usize },
+        #[error("Commit {commit} holds unexpected entry {name}")]
+        UnexpectedEntry { commit: git2::Oid, name: String },
+        #[error("Event blob of commit {commit} not found")]
+        MissingBlob { commit: git2::Oid },
+        #[error("Commit {commit} does not hold a KERI event: {reason}")]
+        NotAnEvent { commit: git2::Oid, reason: String },
+        #[error("Commit {commit} has {count} parents, expected {expected}")]
+        Parents {
+            commit: git2::Oid,
+            count: usize,
+            expected: usize,
+        },
+        #[error("Event of commit {commit} belongs to another identifier")]
+        Identifier { commit: git2::Oid },
+        #[error("Event of commit {commit} has sn {got}, expected {expected}")]
+        Sn {
+            commit: git2::Oid,
+            expected: u64,
+            got: u64,
+        },
+        #[error("Prior digest of the event of commit {commit} is not the parent event digest")]
+        PriorDigest { commit: git2::Oid },
+        #[error("Event of commit {commit} has an invalid SAID")]
+        Said { commit: git2::Oid },
+        #[error("Event of commit {commit} can't be applied to the key state: {reason}")]
+        KeyState {
+            commit: git2::Oid,
+            reason: keri::error::Error,
+        },
+        #[error("Event of commit {commit} has invalid signatures")]
+        Signatures { commit: git2::Oid },
     }
 
     #[derive(Debug, Error)]
     pub enum KeriError {
         #[error(transparent)]
         Git(#[from] git2::Error),
-        #[error("No head found for KEL")]
-        EmptyLog,
-        #[error("KERI log truncated")]
-        TruncatedLog,
+        #[error(transparent)]
+        Integrity(#[from] Integrity),
         #[error(transparent)]
         FindRef(#[from] FindEntry),
         #[error(transparent)]
         MsgParseError(#[from] std::str::Utf8Error),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Append {
+        #[error(transparent)]
+        Git(#[from] git2::Error),
+        #[error(transparent)]
+        FindRef(#[from] FindEntry),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+        #[error(transparent)]
+        RefFormat(#[from] git_ref_format::Error),
+        // The write errors are associated types of `git_storage::Write`,
+        // see the note on `FindEntry`
+        #[error("Failed to write KERI event blob")]
+        WriteBlob,
+        #[error("Failed to write KERI event tree")]
+        WriteTree,
+        #[error("Failed to write KERI event commit")]
+        WriteCommit,
+        #[error("Failed to update KEL ref")]
+        Update,
+        #[error(transparent)]
+        Log(#[from] KeriError),
+        #[error("First event of a KEL must be an inception event")]
+        NotInception,
+        #[error("Expected event with sn {expected}, got sn {got}")]
+        OutOfOrder { expected: u64, got: u64 },
+        #[error("Prior event digest does not match the log head")]
+        PriorDigestMismatch,
+        #[error("KEL head moved concurrently")]
+        Conflict,
+        #[error("Log head is not an ancestor of the new head")]
+        NotFastForward,
+        #[error("The inception event can't be removed from a KEL")]
+        RemoveInception,
     }
 }
 
@@ -63,125 +174,1040 @@ impl<'k> KeriStore<'k> {
         Ok(KeriStore { storage })
     }
 
-    pub fn log_head(&self) -> Result<git_storage::Commit, error::FindEntry> {
-        //let head_ref_str = RefString::try_from("rad/keri/id")?;
+    /// Ref `name` inside the KERI refs directory of the `id` namespace, i.e.
+    /// `refs/namespaces/<id>/refs/keri/<name>`
+    fn keri_ref(
+        id: &IdentifierPrefix,
+        name: &str,
+    ) -> Result<Qualified<'static>, git_ref_format::Error> {
+        let refstring = RefString::try_from(format!(
+            "refs/namespaces/{}/{}/{}",
+            id.to_str(),
+            KERI_REFS,
+            name
+        ))?;
+        // Always starts with `refs/`
+        Ok(Qualified::from_refstr(refstring).unwrap())
+    }
 
-        // TODO hardcoded ref name: take out to a const
-        let lit: String = String::from("rad/keri/id");
-        let parsed: &RefStr = lit.as_str().try_into().unwrap();
-        let keri_ref = Qualified::from_refstr(parsed).unwrap();
-        let head_ref = match self.storage.read_only().find_reference(&keri_ref)? {
+    fn person_log_ref(id: &IdentifierPrefix) -> Result<Qualified<'static>, git_ref_format::Error> {
+        Self::keri_ref(id, KERI_LOG)
+    }
+
+    fn project_log_ref(id: &IdentifierPrefix) -> Result<Qualified<'static>, git_ref_format::Error> {
+        let refstring = RefString::try_from(format!(
+            "refs/namespaces/{}/{}",
+            id.to_str(),
+            PROJECT_LOG_REF
+        ))?;
+        // Always starts with `refs/`
+        Ok(Qualified::from_refstr(refstring).unwrap())
+    }
+
+    /// Ref holding the KEL of `id`, either a person or a project KEL, and
+    /// its head if it exists
+    fn log_ref(
+        &self,
+        id: &IdentifierPrefix,
+    ) -> Result<(Qualified<'static>, Option<Commit>), error::FindEntry> {
+        let project = Self::project_log_ref(id)?;
+        match self.find_head(&project)? {
+            Some(head) => Ok((project, Some(head))),
+            None => {
+                let person = Self::person_log_ref(id)?;
+                let head = self.find_head(&person)?;
+                Ok((person, head))
+            }
+        }
+    }
+
+    /// Identifiers for which a KEL is stored, projects included
+    pub fn identifiers(&self) -> Result<Vec<IdentifierPrefix>, error::FindEntry> {
+        let mut identifiers = self.namespaces_with(&format!("{}/{}", KERI_REFS, KERI_LOG))?;
+        identifiers.append(&mut self.projects()?);
+        Ok(identifiers)
+    }
+
+    /// Project identifiers for which a KEL is stored
+    pub fn projects(&self) -> Result<Vec<IdentifierPrefix>, error::FindEntry> {
+        self.namespaces_with(PROJECT_LOG_REF)
+    }
+
+    /// Identifiers whose namespace holds the KERI ref `name`
+    pub fn identifiers_with(&self, name: &str) -> Result<Vec<IdentifierPrefix>, error::FindEntry> {
+        self.namespaces_with(&format!("{}/{}", KERI_REFS, name))
+    }
+
+    /// Namespaces holding the ref `name`
+    fn namespaces_with(&self, name: &str) -> Result<Vec<IdentifierPrefix>, error::FindEntry> {
+        let pattern = PatternString::try_from(format!("refs/namespaces/*/{}", name))?;
+        let references = self
+            .storage
+            .read_only()
+            .references_glob(glob::RefspecMatcher::from(pattern))
+            .map_err(|_| error::FindEntry::Iter)?;
+
+        references
+            .map(|r| {
+                let r = r.map_err(|_| error::FindEntry::Iter)?;
+                let name = r.name.as_str();
+                let namespace = name
+                    .strip_prefix("refs/namespaces/")
+                    .and_then(|rest| rest.split('/').next())
+                    .ok_or_else(|| error::FindEntry::Namespace(name.to_string()))?;
+                Ok(namespace.parse()?)
+            })
+            .collect()
+    }
+
+    fn find_head(&self, name: &Qualified) -> Result<Option<Commit>, error::FindEntry> {
+        let head_ref = match self.storage.read_only().find_reference(name)? {
             Some(head) => head,
-            None => return Err(error::FindEntry::Empty),
+            None => return Ok(None),
         };
 
         let head_oid = match head_ref.target {
             git_storage::Target::Direct { oid } => oid,
-            git_storage::Target::Symbolic { name } => todo!(),
-        };
-        let head_commit = match self.storage.read_only().find_commit(head_oid) {
-            Ok(commit) => commit,
-            Err(_) => return Err(error::FindEntry::FindCommit),
+            git_storage::Target::Symbolic { name: target } => {
+                return Err(error::FindEntry::Symbolic {
+                    name: name.to_string(),
+                    target: target.to_string(),
+                })
+            }
         };
-
-        match head_commit {
-            Some(commit) => Ok(commit),
-            None => Err(error::FindEntry::Empty),
+        match self.storage.read_only().find_commit(head_oid) {
+            Ok(commit) => Ok(commit),
+            Err(_) => Err(error::FindEntry::FindCommit),
         }
     }
 
+    pub fn log_head(&self, id: &IdentifierPrefix) -> Result<git_storage::Commit, error::FindEntry> {
+        self.log_ref(id)?.1.ok_or(error::FindEntry::Empty)
+    }
+
+    /// Event with sequence number `sn` of the KEL of `id`, if any
     pub fn log_entry_sn(
         &self,
+        id: &IdentifierPrefix,
         sn: u64,
     ) -> Result<Option<TimestampedSignedEventMessage>, error::KeriError> {
-        let head = self.log_head()?;
+        match self.log_commit_sn(Some(self.log_head(id)?), sn)? {
+            Some(commit) => Ok(Some(TimestampedSignedEventMessage::new(
+                self.read_entry(&commit)?,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the event of a KEL commit
+    ///
+    /// The commit tree must hold a single `KERI_EVENT_ENTRY` blob, made of
+    /// exactly one signed KERI event.
+    fn read_entry(&self, commit: &Commit) -> Result<SignedEventMessage, error::Integrity> {
+        let oid = commit.id();
+        let tree = commit.tree()?;
+        let entry = match tree.get(0) {
+            Some(entry) if tree.len() == 1 => entry,
+            _ => {
+                return Err(error::Integrity::EntryCount {
+                    commit: oid,
+                    count: tree.len(),
+                })
+            }
+        };
+        if entry.name() != Some(KERI_EVENT_ENTRY) || entry.kind() != Some(ObjectType::Blob) {
+            return Err(error::Integrity::UnexpectedEntry {
+                commit: oid,
+                name: String::from_utf8_lossy(entry.name_bytes()).into_owned(),
+            });
+        }
+        let blob = self
+            .storage
+            .find_blob(entry.id().into())
+            .ok()
+            .flatten()
+            .ok_or(error::Integrity::MissingBlob { commit: oid })?;
 
-        let mut msg: Option<TimestampedSignedEventMessage> =
-            self.log_entry_in_commit(Some(sn), &head)?;
+        let not_an_event = |reason: String| error::Integrity::NotAnEvent {
+            commit: oid,
+            reason,
+        };
+        match signed_message(blob.content()) {
+            Ok((rest, data)) if rest.is_empty() => match Message::try_from(data) {
+                Ok(Message::Event(event)) => Ok(*event),
+                Ok(_) => Err(not_an_event("not a key event".into())),
+                Err(e) => Err(not_an_event(e.to_string())),
+            },
+            Ok(_) => Err(not_an_event("data after the event".into())),
+            Err(e) => Err(not_an_event(e.to_string())),
+        }
+    }
 
-        while msg.is_none() {
-            msg = head
-                .parents()
-                .find_map(|c| self.log_entry_in_commit(Some(sn), &c).ok()?);
+    /// Collect the KEL reachable from `commit` (or the log head of `id`),
+    /// newest event first
+    ///
+    /// Each commit must hold exactly one event, but the chain itself is not
+    /// verified, see `verified_log`.
+    pub fn log_entries(
+        &self,
+        id: &IdentifierPrefix,
+        commit: Option<Commit>,
+    ) -> Result<Vec<TimestampedSignedEventMessage>, error::KeriError> {
+        let mut next = match commit {
+            None => Some(self.log_head(id)?),
+            Some(c) => Some(c),
+        };
+
+        let mut messages = Vec::new();
+        while let Some(commit) = next {
+            messages.push(TimestampedSignedEventMessage::new(
+                self.read_entry(&commit)?,
+            ));
+            next = commit.parents().next();
         }
 
-        Ok(msg)
+        Ok(messages)
     }
 
-    fn log_entry_in_commit(
+    /// Collect and verify the KEL reachable from `commit` (or the log head
+    /// of `id`), oldest event first
+    ///
+    /// Every commit must hold exactly one event of `id` and, except for the
+    /// inception, have a single parent holding the prior event. The sns must
+    /// be contiguous from 0, each event digest must be bound to its content
+    /// and the signatures must satisfy the key state the event establishes.
+    /// The first violation is returned along with the offending commit.
+    pub fn verified_log(
         &self,
-        sn: Option<u64>,
-        commit: &Commit,
-    ) -> Result<Option<TimestampedSignedEventMessage>, error::KeriError> {
-        let tree = commit.tree()?;
-        let msg: Option<TimestampedSignedEventMessage> = tree.into_iter().find_map(|t| {
-            if t.kind() == Some(ObjectType::Blob) {
-                let blob_id = t.id();
-                let blob = match self.storage.find_blob(blob_id.into()) {
-                    Ok(Some(blob)) => blob,
-                    Ok(_) => return None,  // TODO This is a silent error
-                    Err(_) => return None, // TODO This is a silent error
-                };
-                if let Ok(msg_string) = std::str::from_utf8(blob.content()) {
-                    if let Ok(msg) =
-                        serde_json::from_str::<TimestampedSignedEventMessage>(msg_string)
-                    {
-                        if sn.is_some() {
-                            if msg.signed_event_message.event_message.event.get_sn() == sn? {
-                                Some(msg)
-                            } else {
-                                None
-                            }
-                        } else {
-                            Some(msg)
-                        }
-                    } else {
-                        None // String is not a KERI message TODO this is a silent error, because we should only find KERI messages in this chain
+        id: &IdentifierPrefix,
+        commit: Option<Commit>,
+    ) -> Result<Vec<SignedEventMessage>, error::Integrity> {
+        let head = match commit {
+            None => self.log_head(id)?,
+            Some(c) => c,
+        };
+        let mut commits =
+            std::iter::successors(Some(head), |c| c.parents().next()).collect::<Vec<_>>();
+        commits.reverse();
+
+        let mut state = IdentifierState::default();
+        let mut log: Vec<SignedEventMessage> = Vec::with_capacity(commits.len());
+        for (expected, commit) in (0u64..).zip(&commits) {
+            let oid = commit.id();
+            let parents = if expected == 0 { 0 } else { 1 };
+            if commit.parent_count() != parents {
+                return Err(error::Integrity::Parents {
+                    commit: oid,
+                    count: commit.parent_count(),
+                    expected: parents,
+                });
+            }
+
+            let event = self.read_entry(commit)?;
+            let message = &event.event_message;
+            if &message.event.get_prefix() != id {
+                return Err(error::Integrity::Identifier { commit: oid });
+            }
+            let sn = message.event.get_sn();
+            if sn != expected {
+                return Err(error::Integrity::Sn {
+                    commit: oid,
+                    expected,
+                    got: sn,
+                });
+            }
+            let prior = match message.event.get_event_data() {
+                EventData::Icp(_) | EventData::Dip(_) => None,
+                EventData::Rot(rot) | EventData::Drt(rot) => Some(rot.previous_event_hash),
+                EventData::Ixn(ixn) => Some(ixn.previous_event_hash),
+            };
+            let chained = match (prior, log.last()) {
+                (None, None) => true,
+                (Some(prior), Some(parent)) => parent.event_message.check_digest(&prior)?,
+                _ => false,
+            };
+            if !chained {
+                return Err(error::Integrity::PriorDigest { commit: oid });
+            }
+            if !message.verify_said()? {
+                return Err(error::Integrity::Said { commit: oid });
+            }
+
+            state = state
+                .apply(message)
+                .map_err(|reason| error::Integrity::KeyState {
+                    commit: oid,
+                    reason,
+                })?;
+            if !matches!(
+                state
+                    .current
+                    .verify(&message.serialize()?, &event.signatures),
+                Ok(true)
+            ) {
+                return Err(error::Integrity::Signatures { commit: oid });
+            }
+
+            log.push(event);
+        }
+
+        Ok(log)
+    }
+
+    /// Commit `event` on top of the log head and move the KEL ref to it
+    ///
+    /// The event must directly follow the head: its sn is the next one and its
+    /// prior digest is the digest of the head event. The ref is updated only
+    /// if it still points to the head that was checked, otherwise
+    /// `error::Append::Conflict` is returned and the event has to be
+    /// re-validated against the new head.
+    pub fn append(&self, event: &SignedEventMessage) -> Result<git2::Oid, error::Append> {
+        let (name, head) = self.log_ref(&event.event_message.event.get_prefix())?;
+        self.append_at(name, head.clone(), head, event)
+    }
+
+    /// Start the project KEL with the inception `event`
+    ///
+    /// Later events of the project are added with `append`.
+    pub fn append_project(&self, event: &SignedEventMessage) -> Result<git2::Oid, error::Append> {
+        let name = Self::project_log_ref(&event.event_message.event.get_prefix())?;
+        let head = self.find_head(&name)?;
+        self.append_at(name, head.clone(), head, event)
+    }
+
+    /// Commit `event` in place of the events from its sn on
+    ///
+    /// A recovery rotation supersedes the interaction events at and after
+    /// its sn, which have to be the events of `superseded`, otherwise
+    /// `error::Append::Conflict` is returned. It is committed on top of the
+    /// event it follows, which must be its prior event, and the KEL ref is
+    /// moved to it in a single update, only if it still points to the head
+    /// that was checked.
+    pub fn supersede(
+        &self,
+        event: &SignedEventMessage,
+        superseded: &[SignedEventMessage],
+    ) -> Result<git2::Oid, error::Append> {
+        let id = event.event_message.event.get_prefix();
+        let (name, head) = self.log_ref(&id)?;
+        let sn = event.event_message.event.get_sn();
+        let parent = match sn.checked_sub(1) {
+            Some(parent_sn) => self.log_commit_sn(head.clone(), parent_sn)?,
+            None => None,
+        };
+        let parent_id = parent.as_ref().map(|parent| parent.id());
+        let mut next = head.clone();
+        while let Some(commit) = next {
+            if Some(commit.id()) == parent_id {
+                break;
+            }
+            let entry = self.read_entry(&commit).map_err(error::KeriError::from)?;
+            if !superseded.contains(&entry) {
+                return Err(error::Append::Conflict);
+            }
+            next = commit.parents().next();
+        }
+        self.append_at(name, head, parent, event)
+    }
+
+    /// Drop the events from `sn` on from the KEL of `id`
+    ///
+    /// The KEL ref is moved back to the event before `sn`, if the KEL is
+    /// that long, so the dropped commits are no longer reachable from it.
+    pub fn rewind(&self, id: &IdentifierPrefix, sn: u64) -> Result<(), error::Append> {
+        let parent_sn = sn.checked_sub(1).ok_or(error::Append::RemoveInception)?;
+        let (name, head) = self.log_ref(id)?;
+        let head = match head {
+            Some(head) => head,
+            None => return Ok(()),
+        };
+        let parent = match self.log_commit_sn(Some(head.clone()), parent_sn)? {
+            Some(parent) if parent.id() != head.id() => parent,
+            _ => return Ok(()),
+        };
+
+        let applied = self
+            .storage
+            .update([Update::Direct {
+                name,
+                target: parent.id().into(),
+                no_ff: Policy::Allow,
+                previous: PreviousValue::MustExistAndMatch(head.id().into()),
+            }])
+            .map_err(|_| error::Append::Update)?;
+        if !applied.rejected.is_empty() {
+            return Err(error::Append::Conflict);
+        }
+        Ok(())
+    }
+
+    /// Commit holding the event with sequence number `sn` on the
+    /// first-parent chain of `head`
+    fn log_commit_sn(
+        &self,
+        head: Option<Commit>,
+        sn: u64,
+    ) -> Result<Option<Commit>, error::KeriError> {
+        let mut next = head;
+        while let Some(commit) = next {
+            match self.read_entry(&commit)?.event_message.event.get_sn() {
+                event_sn if event_sn == sn => return Ok(Some(commit)),
+                // Older events only have smaller sns
+                event_sn if event_sn < sn => return Ok(None),
+                _ => next = commit.parents().next(),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Commit `event` on top of `parent` and move the KEL ref `name` from
+    /// `head` to it
+    ///
+    /// `parent` is the head itself, unless events are superseded.
+    fn append_at(
+        &self,
+        name: Qualified<'static>,
+        head: Option<Commit>,
+        parent: Option<Commit>,
+        event: &SignedEventMessage,
+    ) -> Result<git2::Oid, error::Append> {
+        let id = event.event_message.event.get_prefix();
+
+        let sn = event.event_message.event.get_sn();
+        let prior = match event.event_message.event.get_event_data() {
+            EventData::Icp(_) | EventData::Dip(_) => None,
+            EventData::Rot(rot) | EventData::Drt(rot) => Some(rot.previous_event_hash),
+            EventData::Ixn(ixn) => Some(ixn.previous_event_hash),
+        };
+        match (&parent, prior) {
+            (None, None) if sn == 0 => (),
+            (None, _) => return Err(error::Append::NotInception),
+            (Some(_), None) => {
+                return Err(error::Append::OutOfOrder {
+                    expected: 0,
+                    got: sn,
+                })
+            }
+            (Some(parent), Some(prior)) => {
+                let parent_event = self
+                    .read_entry(parent)
+                    .map_err(error::KeriError::from)?
+                    .event_message;
+                let expected = parent_event.event.get_sn() + 1;
+                if sn != expected {
+                    return Err(error::Append::OutOfOrder { expected, got: sn });
+                }
+                if !parent_event.check_digest(&prior)? {
+                    return Err(error::Append::PriorDigestMismatch);
+                }
+            }
+        };
+        let previous = match &head {
+            Some(head) => PreviousValue::MustExistAndMatch(head.id().into()),
+            None => PreviousValue::MustNotExist,
+        };
+        // superseded events are dropped, which isn't a fast-forward
+        let no_ff = match (&head, &parent) {
+            (Some(head), Some(parent)) if head.id() != parent.id() => Policy::Allow,
+            _ => Policy::Reject,
+        };
+
+        let raw = SignedEventData::from(event).to_cesr()?;
+        let blob = self
+            .storage
+            .write_blob(&raw)
+            .map_err(|_| error::Append::WriteBlob)?;
+        let message = format!("{} {}", id.to_str(), sn);
+
+        self.commit_tree(
+            name,
+            parent.as_ref(),
+            TreeBuilder::new().insert(KERI_EVENT_ENTRY.to_string(), blob, FileMode::Blob),
+            &message,
+            previous,
+            no_ff,
+        )
+    }
+
+    /// Move the KEL ref of `id` to `remote`
+    ///
+    /// The log head, if any, must be on the first-parent chain of `remote`,
+    /// otherwise `error::Append::NotFastForward` is returned. The commits of
+    /// `remote` are expected to be already validated.
+    pub fn fast_forward(
+        &self,
+        id: &IdentifierPrefix,
+        remote: &Commit,
+    ) -> Result<git2::Oid, error::Append> {
+        let (name, head) = self.log_ref(id)?;
+        let previous = match &head {
+            Some(head) => {
+                let descends = std::iter::successors(Some(remote.clone()), |c| c.parents().next())
+                    .any(|c| c.id() == head.id());
+                if !descends {
+                    return Err(error::Append::NotFastForward);
+                }
+                PreviousValue::MustExistAndMatch(head.id().into())
+            }
+            None => PreviousValue::MustNotExist,
+        };
+
+        let applied = self
+            .storage
+            .update([Update::Direct {
+                name,
+                target: remote.id().into(),
+                no_ff: Policy::Reject,
+                previous,
+            }])
+            .map_err(|_| error::Append::Update)?;
+        if !applied.rejected.is_empty() {
+            return Err(error::Append::Conflict);
+        }
+
+        Ok(remote.id())
+    }
+
+    /// Add the blob `entry` with `data` to the tree of the KERI ref `name` of
+    /// the `id` namespace
+    ///
+    /// Entries already present in the tree are kept, unless one has the same
+    /// name as `entry`, in which case it is replaced.
+    pub fn insert_entry(
+        &self,
+        id: &IdentifierPrefix,
+        name: &str,
+        entry: &str,
+        data: &[u8],
+    ) -> Result<git2::Oid, error::Append> {
+        let name = Self::keri_ref(id, name)?;
+        let head = self.find_head(&name)?;
+
+        let mut builder = TreeBuilder::new();
+        if let Some(head) = &head {
+            for e in head.tree()?.iter() {
+                if let Some(e_name) = e.name() {
+                    if e_name != entry {
+                        builder = builder.insert(e_name.to_string(), e.id().into(), FileMode::Blob);
                     }
-                } else {
-                    None // Blob is not a string
                 }
-            } else {
-                None // This is something else, but log messages are stored as blobs
             }
-        });
+        }
+        let blob = self
+            .storage
+            .write_blob(data)
+            .map_err(|_| error::Append::WriteBlob)?;
+        let builder = builder.insert(entry.to_string(), blob, FileMode::Blob);
 
-        Ok(msg)
+        let previous = match &head {
+            Some(head) => PreviousValue::MustExistAndMatch(head.id().into()),
+            None => PreviousValue::MustNotExist,
+        };
+        self.commit_tree(
+            name,
+            head.as_ref(),
+            builder,
+            entry,
+            previous,
+            Policy::Reject,
+        )
     }
 
-    pub fn log_entries(
+    /// Remove the blob `entry` from the tree of the KERI ref `name` of the
+    /// `id` namespace
+    ///
+    /// Nothing is committed if the tree holds no such entry.
+    pub fn remove_entry(
         &self,
-        commit: Option<Commit>,
-    ) -> Result<Vec<TimestampedSignedEventMessage>, error::KeriError> {
-        let head: Commit;
-        match commit {
-            None => head = self.log_head()?,
-            Some(c) => head = c,
+        id: &IdentifierPrefix,
+        name: &str,
+        entry: &str,
+    ) -> Result<Option<git2::Oid>, error::Append> {
+        let name = Self::keri_ref(id, name)?;
+        let head = match self.find_head(&name)? {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+
+        let mut builder = TreeBuilder::new();
+        let mut found = false;
+        for e in head.tree()?.iter() {
+            match e.name() {
+                Some(e_name) if e_name == entry => found = true,
+                Some(e_name) => {
+                    builder = builder.insert(e_name.to_string(), e.id().into(), FileMode::Blob)
+                }
+                None => (),
+            }
+        }
+        if !found {
+            return Ok(None);
         }
 
-        let mut messages = Vec::new();
+        let previous = PreviousValue::MustExistAndMatch(head.id().into());
+        let message = format!("remove {}", entry);
+        self.commit_tree(
+            name,
+            Some(&head),
+            builder,
+            &message,
+            previous,
+            Policy::Reject,
+        )
+        .map(Some)
+    }
 
-        messages.push(
-            self.log_entry_in_commit(None, &head)?
-                .ok_or(error::KeriError::EmptyLog)?,
-        );
+    /// Read the blob `entry` from the tree of the KERI ref `name` of the `id`
+    /// namespace
+    pub fn entry(
+        &self,
+        id: &IdentifierPrefix,
+        name: &str,
+        entry: &str,
+    ) -> Result<Option<Vec<u8>>, error::FindEntry> {
+        Ok(self
+            .entries(id, name)?
+            .into_iter()
+            .find_map(|(e_name, data)| (e_name == entry).then(|| data)))
+    }
 
-        let mut parents = head.parents();
-        if parents.len() > 0 {
-            let mut next_msgs = parents
-                .find_map(|c| {
-                    let this_commit_msg = self.log_entry_in_commit(None, &c).ok()??;
-                    let mut child_entries = self.log_entries(Some(c)).ok()?;
+    /// Read all the blobs from the tree of the KERI ref `name` of the `id`
+    /// namespace, together with their entry names
+    pub fn entries(
+        &self,
+        id: &IdentifierPrefix,
+        name: &str,
+    ) -> Result<Vec<(String, Vec<u8>)>, error::FindEntry> {
+        let head = match self.find_head(&Self::keri_ref(id, name)?)? {
+            Some(head) => head,
+            None => return Ok(vec![]),
+        };
 
-                    child_entries.push(this_commit_msg);
-                    child_entries.rotate_right(1);
+        let mut entries = Vec::new();
+        for e in head.tree()?.iter() {
+            if e.kind() != Some(ObjectType::Blob) {
+                continue;
+            }
+            let blob = self
+                .storage
+                .find_blob(e.id().into())
+                .map_err(|_| error::FindEntry::FindCommit)?
+                .ok_or(error::FindEntry::FindCommit)?;
+            if let Some(e_name) = e.name() {
+                entries.push((e_name.to_string(), blob.content().to_vec()));
+            }
+        }
 
-                    Some(child_entries)
-                })
-                .ok_or(error::KeriError::TruncatedLog)?;
-            messages.append(&mut next_msgs)
+        Ok(entries)
+    }
+
+    fn commit_tree(
+        &self,
+        name: Qualified<'static>,
+        parent: Option<&Commit>,
+        builder: TreeBuilder,
+        message: &str,
+        previous: PreviousValue,
+        no_ff: Policy,
+    ) -> Result<git2::Oid, error::Append> {
+        let tree = self
+            .storage
+            .write_tree(builder)
+            .map_err(|_| error::Append::WriteTree)?;
+        let tree = self
+            .storage
+            .find_tree(tree)
+            .map_err(|_| error::Append::WriteTree)?
+            .ok_or(error::Append::WriteTree)?;
+
+        let parents: Vec<&Commit> = parent.into_iter().collect();
+        let commit = self
+            .storage
+            .write_commit(&tree, &parents, message)
+            .map_err(|_| error::Append::WriteCommit)?;
+
+        // The ref only moves if it still points to the expected head, so a
+        // concurrent writer makes us fail instead of forking the log.
+        let applied = self
+            .storage
+            .update([Update::Direct {
+                name,
+                target: commit,
+                no_ff,
+                previous,
+            }])
+            .map_err(|_| error::Append::Update)?;
+        if !applied.rejected.is_empty() {
+            return Err(error::Append::Conflict);
         }
 
-        Ok(messages)
+        Ok(commit.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use keri::derivation::{
+        basic::Basic, self_addressing::SelfAddressing, self_signing::SelfSigning,
+    };
+    use keri::event_message::{event_msg_builder::EventMsgBuilder, EventTypeTag};
+    use keri::prefix::AttachedSignaturePrefix;
+    use keri::signer::{CryptoBox, KeyManager};
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::test_utils::{commit, incept, interact, storage, Result};
+
+    /// Commit holding `event` on top of `parents`
+    fn event_commit(
+        storage: &Write,
+        event: &SignedEventMessage,
+        parents: &[&Commit],
+    ) -> Result<Commit> {
+        let raw = SignedEventData::from(event).to_cesr()?;
+        commit(storage, &[(KERI_EVENT_ENTRY, &raw)], parents)
+    }
+
+    #[test]
+    fn test_append() -> Result<()> {
+        let root = Builder::new().prefix("test-append").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let ixn = interact(&km, &icp, b"first")?;
+        let id = icp.event_message.event.get_prefix();
+
+        assert!(matches!(
+            store.append(&ixn),
+            Err(error::Append::NotInception)
+        ));
+        store.append(&icp)?;
+        assert!(matches!(
+            store.append(&interact(&km, &ixn, b"second")?),
+            Err(error::Append::OutOfOrder {
+                expected: 1,
+                got: 2
+            })
+        ));
+        store.append(&ixn)?;
+
+        let kel = store.log_entries(&id, None)?;
+        assert_eq!(
+            kel.into_iter()
+                .map(|e| e.signed_event_message)
+                .collect::<Vec<_>>(),
+            vec![ixn, icp]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_on_stale_head() -> Result<()> {
+        let root = Builder::new().prefix("test-stale-head").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let id = icp.event_message.event.get_prefix();
+
+        store.append(&icp)?;
+        let (name, stale) = store.log_ref(&id)?;
+        let ixn = interact(&km, &icp, b"first")?;
+        store.append(&ixn)?;
+
+        // A writer that checked the KEL before `ixn` was appended
+        let fork = interact(&km, &icp, b"fork")?;
+        assert!(matches!(
+            store.append_at(name, stale.clone(), stale, &fork),
+            Err(error::Append::Conflict)
+        ));
+        assert_eq!(
+            store.log_entry_sn(&id, 1)?.unwrap().signed_event_message,
+            ixn
+        );
+
+        // `ixn` is not one of the superseded events
+        assert!(matches!(
+            store.supersede(&fork, &[]),
+            Err(error::Append::Conflict)
+        ));
+        assert_eq!(
+            store.log_entry_sn(&id, 1)?.unwrap().signed_event_message,
+            ixn
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verified_log() -> Result<()> {
+        let root = Builder::new().prefix("test-verified-log").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let ixn = interact(&km, &icp, b"first")?;
+        let id = icp.event_message.event.get_prefix();
+        store.append(&icp)?;
+        store.append(&ixn)?;
+
+        assert_eq!(store.verified_log(&id, None)?, vec![icp, ixn]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_count() -> Result<()> {
+        let root = Builder::new().prefix("test-entry-count").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let icp = incept(&CryptoBox::new()?)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        let tip = commit(&storage, &[], &[&icp_commit])?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::EntryCount { commit, count: 0 }) if commit == tip.id()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unexpected_entry() -> Result<()> {
+        let root = Builder::new().prefix("test-unexpected-entry").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        let raw = SignedEventData::from(&interact(&km, &icp, b"first")?).to_cesr()?;
+        let tip = commit(&storage, &[("other", &raw)], &[&icp_commit])?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::UnexpectedEntry { commit, name })
+                if commit == tip.id() && name == "other"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_blob() -> Result<()> {
+        let root = Builder::new().prefix("test-missing-blob").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        let tip = event_commit(&storage, &interact(&km, &icp, b"first")?, &[&icp_commit])?;
+        // Drop the loose blob object of the event
+        let blob = tip.tree()?.get(0).ok_or("empty tree")?.id().to_string();
+        let objects = git2::Repository::open(root.path().join("git"))?
+            .path()
+            .join("objects");
+        std::fs::remove_file(objects.join(&blob[..2]).join(&blob[2..]))?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::MissingBlob { commit }) if commit == tip.id()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_not_an_event() -> Result<()> {
+        let root = Builder::new().prefix("test-not-an-event").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let icp = incept(&CryptoBox::new()?)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        let tip = commit(
+            &storage,
+            &[(KERI_EVENT_ENTRY, b"not an event")],
+            &[&icp_commit],
+        )?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::NotAnEvent { commit, .. }) if commit == tip.id()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parents() -> Result<()> {
+        let root = Builder::new().prefix("test-parents").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        let other = commit(&storage, &[("other", b"other")], &[])?;
+        let tip = event_commit(
+            &storage,
+            &interact(&km, &icp, b"first")?,
+            &[&icp_commit, &other],
+        )?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::Parents { commit, count: 2, expected: 1 }) if commit == tip.id()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_identifier() -> Result<()> {
+        let root = Builder::new().prefix("test-identifier").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let icp = incept(&CryptoBox::new()?)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        let other = CryptoBox::new()?;
+        let ixn = interact(&other, &incept(&other)?, b"first")?;
+        let tip = event_commit(&storage, &ixn, &[&icp_commit])?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::Identifier { commit }) if commit == tip.id()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sn() -> Result<()> {
+        let root = Builder::new().prefix("test-sn").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        let ixn = interact(&km, &icp, b"first")?;
+        let tip = event_commit(&storage, &interact(&km, &ixn, b"second")?, &[&icp_commit])?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::Sn { commit, expected: 1, got: 2 }) if commit == tip.id()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prior_digest() -> Result<()> {
+        let root = Builder::new().prefix("test-prior-digest").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        // Inception of the same identifier, committing to another next key
+        let other_icp = EventMsgBuilder::new(EventTypeTag::Icp)
+            .with_keys(vec![Basic::Ed25519.derive(km.public_key()?)])
+            .with_next_keys(vec![
+                Basic::Ed25519.derive(CryptoBox::new()?.next_public_key()?)
+            ])
+            .build()?
+            .sign(vec![], None);
+        let tip = event_commit(
+            &storage,
+            &interact(&km, &other_icp, b"first")?,
+            &[&icp_commit],
+        )?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::PriorDigest { commit }) if commit == tip.id()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_said() -> Result<()> {
+        let root = Builder::new().prefix("test-said").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        let raw = SignedEventData::from(&interact(&km, &icp, b"first")?).to_cesr()?;
+        // Same size, so the event still parses
+        let tampered = String::from_utf8(raw)?.replace(
+            &SelfAddressing::Blake3_256.derive(b"first").to_str(),
+            &SelfAddressing::Blake3_256.derive(b"other").to_str(),
+        );
+        let tip = commit(
+            &storage,
+            &[(KERI_EVENT_ENTRY, tampered.as_bytes())],
+            &[&icp_commit],
+        )?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::Said { commit }) if commit == tip.id()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_state() -> Result<()> {
+        let root = Builder::new().prefix("test-key-state").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let icp = incept(&CryptoBox::new()?)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        // Rotation to keys the inception didn't commit to
+        let rogue = CryptoBox::new()?;
+        let rot = EventMsgBuilder::new(EventTypeTag::Rot)
+            .with_prefix(&icp.event_message.event.get_prefix())
+            .with_sn(1)
+            .with_previous_event(&icp.event_message.get_digest())
+            .with_keys(vec![Basic::Ed25519.derive(rogue.public_key()?)])
+            .with_next_keys(vec![Basic::Ed25519.derive(rogue.next_public_key()?)])
+            .build()?;
+        let rot = rot.sign(
+            vec![AttachedSignaturePrefix::new(
+                SelfSigning::Ed25519Sha512,
+                rogue.sign(&rot.serialize()?)?,
+                0,
+            )],
+            None,
+        );
+        let tip = event_commit(&storage, &rot, &[&icp_commit])?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::KeyState { commit, .. }) if commit == tip.id()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_signatures() -> Result<()> {
+        let root = Builder::new().prefix("test-signatures").tempdir()?;
+        let storage = storage(root.path())?;
+        let store = KeriStore::open(&storage)?;
+        let icp = incept(&CryptoBox::new()?)?;
+        let icp_commit = event_commit(&storage, &icp, &[])?;
+
+        let forged = interact(&CryptoBox::new()?, &icp, b"forged")?;
+        let tip = event_commit(&storage, &forged, &[&icp_commit])?;
+        assert!(matches!(
+            store.verified_log(&icp.event_message.event.get_prefix(), Some(tip.clone())),
+            Err(error::Integrity::Signatures { commit }) if commit == tip.id()
+        ));
+
+        Ok(())
     }
 }
@@ -1,2 +1,7 @@
+pub mod commit_signature;
 pub mod gitdb;
 pub mod keri_store;
+pub mod migrate;
+
+#[cfg(test)]
+mod test_utils;
@@ -0,0 +1,529 @@
+//! Copy KELs and their receipts between `SledEventDatabase` and
+//! `GitStorageDatabase`
+//!
+//! Every event and receipt is processed by an `EventProcessor` before it is
+//! written to the target, and the outcome of each one is collected in a
+//! [`Report`].
+
+use std::fmt;
+
+use keri::database::{sled::SledEventDatabase, EventDatabase, EventStorage};
+use keri::event_message::signed_event_message::{
+    Message, SignedEventMessage, SignedNontransferableReceipt, SignedTransferableReceipt,
+};
+use keri::prefix::{IdentifierPrefix, Prefix, SelfAddressingPrefix};
+use keri::processor::EventProcessor;
+
+use crate::gitdb::{self, GitStorageDatabase};
+
+/// Event or receipt handled by a migration
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Event {
+        prefix: IdentifierPrefix,
+        sn: u64,
+        digest: SelfAddressingPrefix,
+    },
+    Receipt {
+        prefix: IdentifierPrefix,
+        sn: u64,
+        signers: Vec<IdentifierPrefix>,
+    },
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Item::Event { prefix, sn, digest } => {
+                write!(
+                    f,
+                    "event {} of {} ({})",
+                    sn,
+                    prefix.to_str(),
+                    digest.to_str()
+                )
+            }
+            Item::Receipt {
+                prefix,
+                sn,
+                signers,
+            } => {
+                let signers = signers.iter().map(Prefix::to_str).collect::<Vec<_>>();
+                write!(
+                    f,
+                    "receipt of event {} of {} by {}",
+                    sn,
+                    prefix.to_str(),
+                    signers.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Why an item was not copied
+#[derive(Debug)]
+pub enum Skip {
+    /// The target already holds the item
+    AlreadyPresent,
+    /// An earlier event of the same KEL was not copied
+    AfterSkipped,
+    /// The item was rejected by the `EventProcessor`
+    Invalid(keri::error::Error),
+    /// Reading the item from the source or storing it in the target failed
+    Storage(String),
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Skip::AlreadyPresent => write!(f, "already present"),
+            Skip::AfterSkipped => write!(f, "an earlier event of the KEL was skipped"),
+            Skip::Invalid(e) => write!(f, "invalid: {}", e),
+            Skip::Storage(e) => write!(f, "storage error: {}", e),
+        }
+    }
+}
+
+/// What a migration copied and skipped
+#[derive(Debug, Default)]
+pub struct Report {
+    pub copied: Vec<Item>,
+    pub skipped: Vec<(Item, Skip)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} copied, {} skipped",
+            self.copied.len(),
+            self.skipped.len()
+        )?;
+        for item in &self.copied {
+            writeln!(f, "copied {}", item)?;
+        }
+        for (item, reason) in &self.skipped {
+            writeln!(f, "skipped {}: {}", item, reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl Report {
+    fn record(&mut self, item: Item, outcome: Result<(), Skip>) {
+        match outcome {
+            Ok(()) => self.copied.push(item),
+            Err(reason) => self.skipped.push((item, reason)),
+        }
+    }
+}
+
+/// Copy the KELs and receipts of `source` into `target`
+///
+/// `validator` must be backed by a database of its own, events are
+/// re-validated by processing them into it before they are written to
+/// `target`.
+pub fn sled_to_git(
+    source: &SledEventDatabase,
+    target: &GitStorageDatabase,
+    validator: &EventProcessor,
+) -> Result<Report, keri::error::Error> {
+    let mut report = Report::default();
+
+    let mut kels = Vec::new();
+    for id in source.get_identifiers() {
+        if let Some(kel) = source.get_kel_finalized_events(&id)? {
+            kels.push((id, kel.map(|e| e.signed_event_message).collect()));
+        }
+    }
+    let migrated = copy_kels(kels, &mut report, |event| {
+        let present = target
+            .kel(&event.event_message.event.get_prefix())
+            .map_err(|e| Skip::Storage(e.to_string()))?
+            .iter()
+            .any(|e| e.event_message.get_digest() == event.event_message.get_digest());
+        let receipts = source
+            .get_receipts_nt(&event.event_message.event.get_prefix())
+            .map_err(|e| Skip::Storage(e.to_string()))?
+            .into_iter()
+            .flatten();
+        // Known events are still needed to validate the next ones
+        match gitdb::process_witnessed(validator, event, receipts) {
+            Ok(_) | Err(keri::error::Error::EventDuplicateError) if present => {
+                return Err(Skip::AlreadyPresent)
+            }
+            Ok(_) => (),
+            Err(e) => return Err(Skip::Invalid(e)),
+        }
+        write_event(target, event).map_err(|e| Skip::Storage(e.to_string()))
+    });
+
+    for (id, dig, sn) in migrated {
+        let nt = source
+            .get_receipts_nt(&id)?
+            .into_iter()
+            .flatten()
+            .filter(|rct| rct.body.event.receipted_event_digest == dig);
+        for rct in nt {
+            let outcome = match target.nt_receipts(&id, sn, &dig) {
+                Ok(known) if known_nt(&known, &rct) => Err(Skip::AlreadyPresent),
+                Ok(_) => validator
+                    .process(Message::NontransferableRct(rct.clone()))
+                    .map_err(Skip::Invalid)
+                    .and_then(|_| {
+                        target
+                            .add_receipt_nt(&rct)
+                            .map_err(|e| Skip::Storage(e.to_string()))
+                    }),
+                Err(e) => Err(Skip::Storage(e.to_string())),
+            };
+            report.record(nt_item(&rct), outcome);
+        }
+
+        let t = source
+            .get_receipts_t(&id)?
+            .into_iter()
+            .flatten()
+            .filter(|rct| rct.body.event.receipted_event_digest == dig);
+        for rct in t {
+            let outcome = match target.t_receipts(&id, sn, &dig) {
+                Ok(known) if known.contains(&rct) => Err(Skip::AlreadyPresent),
+                Ok(_) => validator
+                    .process(Message::TransferableRct(Box::new(rct.clone())))
+                    .map_err(Skip::Invalid)
+                    .and_then(|_| {
+                        target
+                            .add_receipt_t(&rct)
+                            .map_err(|e| Skip::Storage(e.to_string()))
+                    }),
+                Err(e) => Err(Skip::Storage(e.to_string())),
+            };
+            report.record(t_item(&rct), outcome);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Copy the KELs and receipts of `source` into the database of `target`
+///
+/// Events and receipts are validated by `target` as it processes them.
+pub fn git_to_sled(
+    source: &GitStorageDatabase,
+    target: &EventProcessor,
+) -> Result<Report, gitdb::error::KeriError> {
+    let mut report = Report::default();
+
+    let mut kels = Vec::new();
+    let mut known_receipts = Vec::new();
+    for id in source.identifiers()? {
+        let kel = source.kel(&id)?;
+        known_receipts.push((id.clone(), receipts_nt(&target.db, &id)?));
+        kels.push((id, kel));
+    }
+    let migrated = copy_kels(kels, &mut report, |event| {
+        let receipts = source
+            .nt_receipts(
+                &event.event_message.event.get_prefix(),
+                event.event_message.event.get_sn(),
+                &event.event_message.get_digest(),
+            )
+            .map_err(|e| Skip::Storage(e.to_string()))?;
+        match gitdb::process_witnessed(target, event, receipts) {
+            Ok(_) => Ok(()),
+            Err(keri::error::Error::EventDuplicateError) => Err(Skip::AlreadyPresent),
+            Err(e) => Err(Skip::Invalid(e)),
+        }
+    });
+
+    for (id, dig, sn) in migrated {
+        let nt_known = known_receipts
+            .iter()
+            .find(|(known, _)| known == &id)
+            .map_or(&[][..], |(_, receipts)| receipts.as_slice());
+        for rct in source.nt_receipts(&id, sn, &dig)? {
+            let outcome = if known_nt(nt_known, &rct) {
+                Err(Skip::AlreadyPresent)
+            } else if known_nt(&receipts_nt(&target.db, &id)?, &rct) {
+                // stored when its event was accepted
+                Ok(())
+            } else {
+                target
+                    .process(Message::NontransferableRct(rct.clone()))
+                    .map(|_| ())
+                    .map_err(Skip::Invalid)
+            };
+            report.record(nt_item(&rct), outcome);
+        }
+
+        let t_known = target
+            .db
+            .get_receipts_t(&id)?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        for rct in source.t_receipts(&id, sn, &dig)? {
+            let outcome = if t_known.contains(&rct) {
+                Err(Skip::AlreadyPresent)
+            } else {
+                target
+                    .process(Message::TransferableRct(Box::new(rct.clone())))
+                    .map(|_| ())
+                    .map_err(Skip::Invalid)
+            };
+            report.record(t_item(&rct), outcome);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Copy the events of `kels` with `copy`, in order within each KEL
+///
+/// An event that can't be copied may depend on another KEL, like a delegated
+/// event on its delegator, so KELs are tried again as long as some progress
+/// is made. Returns the identifier, digest and sn of the events the target
+/// holds afterwards.
+fn copy_kels<F>(
+    kels: Vec<(IdentifierPrefix, Vec<SignedEventMessage>)>,
+    report: &mut Report,
+    mut copy: F,
+) -> Vec<(IdentifierPrefix, SelfAddressingPrefix, u64)>
+where
+    F: FnMut(&SignedEventMessage) -> Result<(), Skip>,
+{
+    let mut pending = kels
+        .into_iter()
+        .map(|(id, kel)| (id, kel.into_iter().peekable(), None))
+        .collect::<Vec<_>>();
+    let mut migrated = Vec::new();
+
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for (id, kel, failure) in pending.iter_mut() {
+            while let Some(event) = kel.peek() {
+                let item = event_item(event);
+                let outcome = match copy(event) {
+                    Ok(()) => Ok(()),
+                    Err(Skip::AlreadyPresent) => Err(Skip::AlreadyPresent),
+                    Err(reason) => {
+                        *failure = Some(reason);
+                        break;
+                    }
+                };
+                migrated.push((
+                    id.clone(),
+                    event.event_message.get_digest(),
+                    event.event_message.event.get_sn(),
+                ));
+                report.record(item, outcome);
+                *failure = None;
+                kel.next();
+                progress = true;
+            }
+        }
+    }
+
+    for (_, mut kel, failure) in pending {
+        if let Some(event) = kel.next() {
+            let reason = failure.unwrap_or(Skip::AfterSkipped);
+            report.skipped.push((event_item(&event), reason));
+        }
+        for event in kel {
+            report
+                .skipped
+                .push((event_item(&event), Skip::AfterSkipped));
+        }
+    }
+
+    migrated
+}
+
+fn receipts_nt(
+    db: &SledEventDatabase,
+    id: &IdentifierPrefix,
+) -> Result<Vec<SignedNontransferableReceipt>, keri::error::Error> {
+    Ok(db.get_receipts_nt(id)?.into_iter().flatten().collect())
+}
+
+fn write_event(
+    target: &GitStorageDatabase,
+    event: &SignedEventMessage,
+) -> Result<(), gitdb::error::KeriError> {
+    let prefix = event.event_message.event.get_prefix();
+    let dig = event.event_message.get_digest();
+    target.log_event(
+        &prefix,
+        &dig,
+        &event.event_message.serialize()?,
+        &event.signatures,
+    )?;
+    target.finalise_event(&prefix, event.event_message.event.get_sn(), &dig)
+}
+
+// Receipts are stored one couplet at a time in git
+fn known_nt(known: &[SignedNontransferableReceipt], rct: &SignedNontransferableReceipt) -> bool {
+    rct.couplets
+        .iter()
+        .all(|couplet| known.iter().any(|k| k.couplets.contains(couplet)))
+}
+
+fn event_item(event: &SignedEventMessage) -> Item {
+    Item::Event {
+        prefix: event.event_message.event.get_prefix(),
+        sn: event.event_message.event.get_sn(),
+        digest: event.event_message.get_digest(),
+    }
+}
+
+fn nt_item(rct: &SignedNontransferableReceipt) -> Item {
+    Item::Receipt {
+        prefix: rct.body.event.prefix.clone(),
+        sn: rct.body.event.sn,
+        signers: rct
+            .couplets
+            .iter()
+            .map(|(signer, _)| IdentifierPrefix::Basic(signer.clone()))
+            .collect(),
+    }
+}
+
+fn t_item(rct: &SignedTransferableReceipt) -> Item {
+    Item::Receipt {
+        prefix: rct.body.event.prefix.clone(),
+        sn: rct.body.event.sn,
+        signers: vec![rct.validator_seal.prefix.clone()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use keri::derivation::{basic::Basic, self_signing::SelfSigning};
+    use keri::event::{receipt::Receipt, sections::seal::EventSeal, SerializationFormats};
+    use keri::prefix::AttachedSignaturePrefix;
+    use keri::signer::{CryptoBox, KeyManager};
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::test_utils::{incept, interact, storage, Result};
+
+    fn processor(root: &Path, name: &str) -> Result<EventProcessor> {
+        let db = SledEventDatabase::new(root.join(name).as_path())?;
+        Ok(EventProcessor::new(Arc::new(db)))
+    }
+
+    fn receipt_body(event: &SignedEventMessage) -> Result<keri::event::EventMessage<Receipt>> {
+        Ok(Receipt {
+            prefix: event.event_message.event.get_prefix(),
+            sn: event.event_message.event.get_sn(),
+            receipted_event_digest: event.event_message.get_digest(),
+        }
+        .to_message(SerializationFormats::JSON)?)
+    }
+
+    fn assert_copied(report: &Report, expected: &[Item]) {
+        assert!(report.skipped.is_empty(), "{}", report);
+        assert_eq!(report.copied.len(), expected.len(), "{}", report);
+        for item in expected {
+            assert!(report.copied.contains(item), "{} not copied", item);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<()> {
+        let root = Builder::new().prefix("test-migrate").tempdir()?;
+        let source = processor(root.path(), "source")?;
+
+        // A KEL receipted by a witness and by a validator, whose KEL is
+        // migrated as well
+        let km = CryptoBox::new()?;
+        let icp = incept(&km)?;
+        let ixn = interact(&km, &icp, b"first")?;
+        let validator_km = CryptoBox::new()?;
+        let validator_icp = incept(&validator_km)?;
+        for event in [&icp, &ixn, &validator_icp] {
+            source.process(Message::Event(Box::new(event.clone())))?;
+        }
+
+        let serialized = ixn.event_message.serialize()?;
+        let witness_km = CryptoBox::new()?;
+        let witness = Basic::Ed25519.derive(witness_km.public_key()?);
+        let nt = SignedNontransferableReceipt::new(
+            &receipt_body(&ixn)?,
+            vec![(
+                witness.clone(),
+                SelfSigning::Ed25519Sha512.derive(witness_km.sign(&serialized)?),
+            )],
+        );
+        source.process(Message::NontransferableRct(nt))?;
+        let validator = validator_icp.event_message.event.get_prefix();
+        let t = SignedTransferableReceipt::new(
+            receipt_body(&ixn)?,
+            EventSeal {
+                prefix: validator.clone(),
+                sn: 0,
+                event_digest: validator_icp.event_message.get_digest(),
+            },
+            vec![AttachedSignaturePrefix::new(
+                SelfSigning::Ed25519Sha512,
+                validator_km.sign(&serialized)?,
+                0,
+            )],
+        );
+        source.process(Message::TransferableRct(Box::new(t)))?;
+
+        let id = icp.event_message.event.get_prefix();
+        let expected = [
+            event_item(&icp),
+            event_item(&ixn),
+            event_item(&validator_icp),
+            Item::Receipt {
+                prefix: id.clone(),
+                sn: 1,
+                signers: vec![IdentifierPrefix::Basic(witness)],
+            },
+            Item::Receipt {
+                prefix: id.clone(),
+                sn: 1,
+                signers: vec![validator],
+            },
+        ];
+
+        let storage = storage(root.path())?;
+        let git = GitStorageDatabase::open(&storage)?;
+        let validating = processor(root.path(), "validator")?;
+        assert_copied(&sled_to_git(&source.db, &git, &validating)?, &expected);
+        assert_eq!(git.kel(&id)?, vec![icp.clone(), ixn.clone()]);
+
+        // Nothing left to copy
+        let again = sled_to_git(&source.db, &git, &validating)?;
+        assert!(again.copied.is_empty(), "{}", again);
+        assert_eq!(again.skipped.len(), expected.len(), "{}", again);
+        assert!(again
+            .skipped
+            .iter()
+            .all(|(_, reason)| matches!(reason, Skip::AlreadyPresent)));
+
+        let target = processor(root.path(), "target")?;
+        assert_copied(&git_to_sled(&git, &target)?, &expected);
+        let kel = target
+            .db
+            .get_kel_finalized_events(&id)?
+            .ok_or("KEL not migrated")?
+            .map(|e| e.signed_event_message)
+            .collect::<Vec<_>>();
+        assert_eq!(kel, vec![icp, ixn]);
+        assert_eq!(receipts_nt(&target.db, &id)?, receipts_nt(&source.db, &id)?);
+        assert_eq!(
+            target.db.get_receipts_t(&id)?.into_iter().flatten().count(),
+            1
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+//! Fixtures shared by the tests of the storage modules
+
+use std::path::Path;
+
+use git2::FileMode;
+use git_storage::odb::{Read as _, TreeBuilder, Write as _};
+use git_storage::{signature::UserInfo, Commit, Write};
+
+use keri::derivation::{basic::Basic, self_addressing::SelfAddressing, self_signing::SelfSigning};
+use keri::event::sections::seal::{DigestSeal, Seal};
+use keri::event_message::event_msg_builder::EventMsgBuilder;
+use keri::event_message::signed_event_message::SignedEventMessage;
+use keri::event_message::EventTypeTag;
+use keri::prefix::AttachedSignaturePrefix;
+use keri::signer::{CryptoBox, KeyManager};
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Git storage in the `git` directory of `root`
+pub fn storage(root: &Path) -> Result<Write> {
+    Ok(Write::open(
+        root.join("git"),
+        UserInfo {
+            name: "Alice Liddell".to_owned(),
+            email: "alice@example.com".to_owned(),
+        },
+    )?)
+}
+
+/// Inception event of a single key identifier controlled by `km`
+pub fn incept(km: &CryptoBox) -> Result<SignedEventMessage> {
+    let icp = EventMsgBuilder::new(EventTypeTag::Icp)
+        .with_keys(vec![Basic::Ed25519.derive(km.public_key()?)])
+        .with_next_keys(vec![Basic::Ed25519.derive(km.next_public_key()?)])
+        .build()?;
+    Ok(icp.sign(vec![signature(km, &icp.serialize()?)?], None))
+}
+
+/// Interaction event following `prior`, anchoring the digest of `payload`
+/// and signed by `km`
+pub fn interact(
+    km: &CryptoBox,
+    prior: &SignedEventMessage,
+    payload: &[u8],
+) -> Result<SignedEventMessage> {
+    let ixn = EventMsgBuilder::new(EventTypeTag::Ixn)
+        .with_prefix(&prior.event_message.event.get_prefix())
+        .with_sn(prior.event_message.event.get_sn() + 1)
+        .with_previous_event(&prior.event_message.get_digest())
+        .with_seal(vec![Seal::Digest(DigestSeal {
+            dig: SelfAddressing::Blake3_256.derive(payload),
+        })])
+        .build()?;
+    Ok(ixn.sign(vec![signature(km, &ixn.serialize()?)?], None))
+}
+
+/// Commit a tree of the `entries` blobs on top of `parents`, without moving
+/// any ref
+pub fn commit(storage: &Write, entries: &[(&str, &[u8])], parents: &[&Commit]) -> Result<Commit> {
+    let mut builder = TreeBuilder::new();
+    for (name, data) in entries {
+        builder = builder.insert(name.to_string(), storage.write_blob(data)?, FileMode::Blob);
+    }
+    let tree = storage
+        .find_tree(storage.write_tree(builder)?)?
+        .ok_or("tree not written")?;
+    let commit = storage.write_commit(&tree, parents, "test")?;
+    Ok(storage.find_commit(commit)?.ok_or("commit not written")?)
+}
+
+fn signature(km: &CryptoBox, data: &[u8]) -> Result<AttachedSignaturePrefix> {
+    Ok(AttachedSignaturePrefix::new(
+        SelfSigning::Ed25519Sha512,
+        km.sign(data)?,
+        0,
+    ))
+}
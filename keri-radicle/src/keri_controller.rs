@@ -1,32 +1,391 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use keri::database::sled::SledEventDatabase;
+use keri::derivation::{basic::Basic, self_signing::SelfSigning};
+use keri::error::Error;
+use keri::event::event_data::EventData;
+use keri::event::receipt::Receipt;
+use keri::event::sections::seal::{EventSeal, Seal, SourceSeal};
+use keri::event::SerializationFormats;
+use keri::event_message::event_msg_builder::EventMsgBuilder;
+use keri::event_message::signature::Signature;
+use keri::event_message::signed_event_message::{Message, SignedNontransferableReceipt};
+use keri::event_message::EventTypeTag;
 use keri::keri::Keri;
+use keri::prefix::{AttachedSignaturePrefix, BasicPrefix, IdentifierPrefix, Prefix};
+use keri::processor::EventProcessor;
 use keri::signer::KeyManager;
 
+use keri_git::gitdb::{self, GitStorageDatabase};
+use keri_git::keri_store::KeriStore;
+
 use git_storage::Write;
 
 use git_ref_format::RefString;
 
 // TODO Since this code will probably live in `heartwood`, this type should
-// be the heartwood SignedRef type. This is just a placeholder.
-pub type SignedRef = RefString;
+// be the heartwood SignedRef type.
+pub use crate::signed_ref::SignedRef;
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Init {
+        #[error(transparent)]
+        Store(#[from] keri_git::keri_store::error::Init),
+        #[error(transparent)]
+        Git(#[from] keri_git::gitdb::error::IO),
+        #[error(transparent)]
+        Log(#[from] keri_git::gitdb::error::KeriError),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+        #[error("No KEL found for the identity")]
+        UnknownIdentity,
+    }
 
-pub struct KeriController<K>
+    #[derive(Debug, Error)]
+    pub enum Inception {
+        #[error("Controller already has an identity")]
+        AlreadyIncepted,
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+        #[error(transparent)]
+        Append(#[from] keri_git::keri_store::error::Append),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Project {
+        #[error("Controller has no identity")]
+        NotIncepted,
+        #[error("Threshold must be between 1 and the number of delegates")]
+        Threshold,
+        #[error("No KEL found for delegate {0}")]
+        UnknownDelegate(String),
+        #[error("Delegate {0} is not controlled by a single key")]
+        DelegateKeys(String),
+        #[error("No KEL found for the project")]
+        UnknownProject,
+        #[error("Controller is not a delegate of the project")]
+        NotADelegate,
+        #[error("Key manager lock poisoned")]
+        MutexPoisoned,
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+        #[error(transparent)]
+        Append(#[from] keri_git::keri_store::error::Append),
+        #[error(transparent)]
+        Log(#[from] keri_git::gitdb::error::KeriError),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Quorum {
+        #[error("No KEL found for the project")]
+        UnknownProject,
+        #[error("Project inception is not receipted by enough delegates")]
+        NotEnoughReceipts,
+        #[error(transparent)]
+        Log(#[from] keri_git::gitdb::error::KeriError),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Sign {
+        #[error("Controller has no identity")]
+        NotIncepted,
+        #[error("Key manager lock poisoned")]
+        MutexPoisoned,
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Validate {
+        #[error("No KEL found for the signer identifier")]
+        UnknownSigner,
+        #[error("Seal does not point to an establishment event of the signer KEL")]
+        InvalidSeal,
+        #[error("Signer does not match the identifier of the seal")]
+        SignerMismatch,
+        #[error(transparent)]
+        Log(#[from] keri_git::gitdb::error::KeriError),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+    }
+}
+
+pub struct KeriController<'s, K>
 where
     K: KeyManager + 'static,
 {
+    store: KeriStore<'s>,
+    git: GitStorageDatabase<'s>,
     identity: Keri<K>,
+    incepted: bool,
 }
 
-impl<K> KeriController<K>
+impl<'s, K> KeriController<'s, K>
 where
     K: KeyManager + 'static,
 {
-    pub fn new(storage: &Write, key_manager: impl KeyManager) -> Self {
-        todo!()
+    /// Open the controller over `storage`
+    ///
+    /// If `prefix` is given, its KEL is replayed from the storage into `db`
+    /// and the identity is assigned to the Controller.
+    pub fn new(
+        storage: &'s Write,
+        db: Arc<SledEventDatabase>,
+        key_manager: K,
+        prefix: Option<IdentifierPrefix>,
+    ) -> Result<Self, error::Init> {
+        let store = KeriStore::open(storage)?;
+        let git = GitStorageDatabase::open(storage)?;
+        let key_manager = Arc::new(Mutex::new(key_manager));
+
+        let incepted = prefix.is_some();
+        let identity = match prefix {
+            Some(prefix) => {
+                if !replay(&git, &EventProcessor::new(Arc::clone(&db)), &prefix)? {
+                    return Err(error::Init::UnknownIdentity);
+                }
+                Keri::open(db, key_manager, prefix)?
+            }
+            None => Keri::new(db, key_manager)?,
+        };
+
+        Ok(Self {
+            store,
+            git,
+            identity,
+            incepted,
+        })
+    }
+
+    /// Prefix of the Controller identity, if it has been incepted
+    pub fn prefix(&self) -> Option<&IdentifierPrefix> {
+        self.incepted.then(|| self.identity.prefix())
     }
 
     /// Start a new identity and assign it to the Controller
-    pub fn inception(&mut self) {
-        todo!()
+    ///
+    /// The inception event is committed to the KERI refs of the storage.
+    pub fn inception(&mut self) -> Result<IdentifierPrefix, error::Inception> {
+        if self.incepted {
+            return Err(error::Inception::AlreadyIncepted);
+        }
+        let icp = self.identity.incept(None)?;
+        self.store.append(&icp)?;
+        self.incepted = true;
+
+        Ok(self.identity.prefix().clone())
+    }
+
+    /// Start a new project identity delegated by the Controller identity
+    ///
+    /// The project is incepted with a delegated inception event, whose seal is
+    /// anchored in the Controller KEL by an interaction event. The inception
+    /// event starts the project KEL under `refs/rad/kel` of the project
+    /// namespace, the interaction event is appended to the Controller KEL.
+    ///
+    /// `delegates` are the identities allowed to change the project,
+    /// `threshold` of them have to agree on a change. The current key of each
+    /// delegate becomes a witness of the project and `threshold` its witness
+    /// tally, so delegates are changed by rotating the project KEL. The
+    /// project is only accepted once `threshold` delegates receipted its
+    /// inception event with [`KeriController::receipt_project`], which the
+    /// Controller does right away if it is one of the delegates.
+    pub fn create_project(
+        &self,
+        delegates: Vec<IdentifierPrefix>,
+        threshold: u64,
+    ) -> Result<IdentifierPrefix, error::Project> {
+        let delegator = self.prefix().ok_or(error::Project::NotIncepted)?.clone();
+        if threshold == 0 || threshold > delegates.len() as u64 {
+            return Err(error::Project::Threshold);
+        }
+        let processor = EventProcessor::new(self.identity.db());
+        let witnesses = delegates
+            .iter()
+            .map(|delegate| self.delegate_key(&processor, delegate))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let key_manager = self.identity.key_manager();
+        let (dip, signatures) = {
+            let km = key_manager
+                .lock()
+                .map_err(|_| error::Project::MutexPoisoned)?;
+            let dip = EventMsgBuilder::new(EventTypeTag::Dip)
+                .with_delegator(&delegator)
+                .with_keys(vec![Basic::Ed25519.derive(km.public_key()?)])
+                .with_next_keys(vec![Basic::Ed25519.derive(km.next_public_key()?)])
+                .with_witness_list(&witnesses)
+                .with_witness_threshold(threshold)
+                .build()?;
+            let signatures = vec![AttachedSignaturePrefix::new(
+                SelfSigning::Ed25519Sha512,
+                km.sign(&dip.serialize()?)?,
+                0,
+            )];
+            (dip, signatures)
+        };
+        let project = dip.event.get_prefix();
+
+        let ixn = self.identity.anchor(vec![Seal::Event(EventSeal {
+            prefix: project.clone(),
+            sn: 0,
+            event_digest: dip.get_digest(),
+        })])?;
+        let dip = dip.sign(
+            signatures,
+            Some(SourceSeal::new(
+                ixn.event_message.event.get_sn(),
+                ixn.event_message.get_digest(),
+            )),
+        );
+        match processor.process(Message::Event(Box::new(dip.clone()))) {
+            // Waiting for the receipts of the delegates
+            Ok(_) | Err(Error::NotEnoughReceiptsError) => (),
+            Err(e) => return Err(e.into()),
+        }
+
+        self.store.append(&ixn)?;
+        self.store.append_project(&dip)?;
+        if delegates.contains(&delegator) {
+            self.receipt_project(&project)?;
+        }
+
+        Ok(project)
+    }
+
+    /// Receipt the inception event of `project` as one of its delegates
+    ///
+    /// The receipt is signed with the current key of the Controller, which
+    /// has to be one of the project witnesses, and stored next to the
+    /// project KEL.
+    pub fn receipt_project(&self, project: &IdentifierPrefix) -> Result<(), error::Project> {
+        let dip = self
+            .git
+            .kel(project)?
+            .into_iter()
+            .next()
+            .ok_or(error::Project::UnknownProject)?;
+        let witnesses = match dip.event_message.event.get_event_data() {
+            EventData::Dip(dip) => dip.inception_data.witness_config.initial_witnesses,
+            _ => return Err(error::Project::UnknownProject),
+        };
+
+        let rct = {
+            let key_manager = self.identity.key_manager();
+            let km = key_manager
+                .lock()
+                .map_err(|_| error::Project::MutexPoisoned)?;
+            let key = Basic::Ed25519.derive(km.public_key()?);
+            if !witnesses.contains(&key) {
+                return Err(error::Project::NotADelegate);
+            }
+            let body = Receipt {
+                prefix: project.clone(),
+                sn: 0,
+                receipted_event_digest: dip.event_message.get_digest(),
+            }
+            .to_message(SerializationFormats::JSON)?;
+            let signature =
+                SelfSigning::Ed25519Sha512.derive(km.sign(&dip.event_message.serialize()?)?);
+            SignedNontransferableReceipt::new(&body, vec![(key, signature)])
+        };
+        self.git.add_receipt_nt(&rct)?;
+        replay(&self.git, &EventProcessor::new(self.identity.db()), project)?;
+
+        Ok(())
+    }
+
+    /// Sign `refs` with the current keys of the Controller identity
+    pub fn sign_refs(
+        &self,
+        refs: BTreeMap<RefString, git2::Oid>,
+    ) -> Result<SignedRef, error::Sign> {
+        let signer = self.prefix().ok_or(error::Sign::NotIncepted)?.clone();
+        let seal = EventProcessor::new(self.identity.db())
+            .get_last_establishment_event_seal(&signer)?
+            .ok_or(error::Sign::NotIncepted)?;
+
+        let signature = self
+            .identity
+            .key_manager()
+            .lock()
+            .map_err(|_| error::Sign::MutexPoisoned)?
+            .sign(&SignedRef::canonical_refs(&refs))?;
+
+        Ok(SignedRef {
+            refs,
+            signer,
+            seal,
+            signatures: vec![AttachedSignaturePrefix::new(
+                SelfSigning::Ed25519Sha512,
+                signature,
+                0,
+            )],
+        })
+    }
+
+    /// Find the refs state agreed on by a quorum of the project delegates
+    ///
+    /// The delegates are the witnesses of the project key state and the
+    /// quorum is its witness tally. Every signed ref has to be made by a
+    /// distinct delegate with the current keys of its KEL, which hold the
+    /// witness key. Returns the refs signed by at least the quorum of
+    /// delegates, if any.
+    pub fn verify_quorum(
+        &self,
+        project: &IdentifierPrefix,
+        signed_refs: &[SignedRef],
+    ) -> Result<Option<BTreeMap<RefString, git2::Oid>>, error::Quorum> {
+        let processor = EventProcessor::new(self.identity.db());
+        if !replay(&self.git, &processor, project)? {
+            return Err(error::Quorum::UnknownProject);
+        }
+        let state = processor
+            .compute_state(project)?
+            .ok_or(error::Quorum::NotEnoughReceipts)?;
+        // A project without threshold still needs one delegate to agree
+        let threshold = state.tally.max(1) as usize;
+
+        let mut signers: Vec<(&BTreeMap<RefString, git2::Oid>, Vec<&IdentifierPrefix>)> =
+            Vec::new();
+        for signed_ref in signed_refs {
+            // Refs with invalid signature don't count
+            if !matches!(self.validate(signed_ref), Ok(true)) {
+                continue;
+            }
+            // Nor do refs signed with keys rotated away since
+            let current = processor.get_last_establishment_event_seal(&signed_ref.signer)?;
+            if current.as_ref() != Some(&signed_ref.seal) {
+                continue;
+            }
+            // Nor refs of non delegates
+            let keys = processor
+                .compute_state(&signed_ref.signer)?
+                .map(|signer| signer.current.public_keys)
+                .unwrap_or_default();
+            if !keys.iter().any(|key| state.witnesses.contains(key)) {
+                continue;
+            }
+            match signers
+                .iter_mut()
+                .find(|(refs, _)| *refs == &signed_ref.refs)
+            {
+                Some((_, ids)) if ids.contains(&&signed_ref.signer) => (),
+                Some((_, ids)) => ids.push(&signed_ref.signer),
+                None => signers.push((&signed_ref.refs, vec![&signed_ref.signer])),
+            }
+        }
+
+        Ok(signers
+            .into_iter()
+            .find(|(_, ids)| ids.len() >= threshold)
+            .map(|(refs, _)| refs.clone()))
     }
 
     /// Validate a signed ref based on the KERI identity
@@ -34,7 +393,193 @@ where
     /// The Controller will use the stored Identity in the signed ref
     /// (*not* the controller identity)
     /// to validate its associated KEL hence validating the signature key
-    pub fn validate(&self, signed_ref: SignedRef) {
-        todo!()
+    ///
+    /// The signing keys are the ones established by the event the seal of
+    /// the signed ref points to, so a ref signed before a rotation stays
+    /// valid after it.
+    pub fn validate(&self, signed_ref: &SignedRef) -> Result<bool, error::Validate> {
+        if signed_ref.signer != signed_ref.seal.prefix {
+            return Err(error::Validate::SignerMismatch);
+        }
+        let processor = EventProcessor::new(self.identity.db());
+        if !replay(&self.git, &processor, &signed_ref.signer)? {
+            return Err(error::Validate::UnknownSigner);
+        }
+
+        let signature =
+            Signature::Transferable(signed_ref.seal.clone(), signed_ref.signatures.clone());
+        let refs = SignedRef::canonical_refs(&signed_ref.refs);
+        match processor.verify(&refs, &signature) {
+            Ok(()) => Ok(true),
+            Err(Error::SignatureVerificationError) | Err(Error::NotEnoughSigsError) => Ok(false),
+            // The seal does not point to an establishment event of the KEL
+            Err(Error::EventOutOfOrderError) | Err(Error::SemanticError(_)) => {
+                Err(error::Validate::InvalidSeal)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Current key of `delegate`, which has to be controlled by a single key
+    fn delegate_key(
+        &self,
+        processor: &EventProcessor,
+        delegate: &IdentifierPrefix,
+    ) -> Result<BasicPrefix, error::Project> {
+        let unknown = || error::Project::UnknownDelegate(delegate.to_str());
+        if !replay(&self.git, processor, delegate)? {
+            return Err(unknown());
+        }
+        let state = processor.compute_state(delegate)?.ok_or_else(unknown)?;
+        match state.current.public_keys.as_slice() {
+            [key] => Ok(key.clone()),
+            _ => Err(error::Project::DelegateKeys(delegate.to_str())),
+        }
+    }
+}
+
+/// Process the KEL of `id` found in `git`, along with the witness receipts
+/// stored for its events
+///
+/// Returns `false` if the store holds no KEL for `id`.
+fn replay(
+    git: &GitStorageDatabase,
+    processor: &EventProcessor,
+    id: &IdentifierPrefix,
+) -> Result<bool, gitdb::error::KeriError> {
+    let kel = git.kel(id)?;
+    if kel.is_empty() {
+        return Ok(false);
+    }
+
+    for event in kel {
+        let receipts = git.nt_receipts(
+            id,
+            event.event_message.event.get_sn(),
+            &event.event_message.get_digest(),
+        )?;
+        match gitdb::process_witnessed(processor, &event, receipts) {
+            // Already known from a previous replay, or still waiting for
+            // witness receipts
+            Ok(()) | Err(Error::EventDuplicateError) | Err(Error::NotEnoughReceiptsError) => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use git_storage::signature::UserInfo;
+    use keri::signer::CryptoBox;
+    use tempfile::Builder;
+
+    use super::*;
+
+    type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+    fn incepted<'s>(
+        storage: &'s Write,
+        db: &Arc<SledEventDatabase>,
+    ) -> Result<KeriController<'s, CryptoBox>> {
+        let mut controller = KeriController::new(storage, Arc::clone(db), CryptoBox::new()?, None)?;
+        controller.inception()?;
+        Ok(controller)
+    }
+
+    #[test]
+    fn test_quorum_of_delegates() -> Result<()> {
+        let root = Builder::new().prefix("test-quorum").tempdir()?;
+        let storage = Write::open(
+            root.path().join("git"),
+            UserInfo {
+                name: "Alice Liddell".to_owned(),
+                email: "alice@example.com".to_owned(),
+            },
+        )?;
+        let db = Arc::new(SledEventDatabase::new(root.path().join("db").as_path())?);
+
+        let alice = incepted(&storage, &db)?;
+        let bob = incepted(&storage, &db)?;
+        let carol = incepted(&storage, &db)?;
+        let delegates = vec![
+            alice.prefix().unwrap().clone(),
+            bob.prefix().unwrap().clone(),
+        ];
+        let project = alice.create_project(delegates, 2)?;
+        // Only Alice receipted the project so far
+        assert!(matches!(
+            carol.verify_quorum(&project, &[]),
+            Err(error::Quorum::NotEnoughReceipts)
+        ));
+        assert!(matches!(
+            carol.receipt_project(&project),
+            Err(error::Project::NotADelegate)
+        ));
+        bob.receipt_project(&project)?;
+
+        let refs: BTreeMap<RefString, git2::Oid> = [(
+            RefString::try_from("refs/heads/main")?,
+            git2::Oid::from_str("4b825dc642cb6eb9a060e54bf8d69288fbee4904")?,
+        )]
+        .into_iter()
+        .collect();
+        let by_alice = alice.sign_refs(refs.clone())?;
+        let by_bob = bob.sign_refs(refs.clone())?;
+        let by_carol = carol.sign_refs(refs.clone())?;
+
+        // Carol is no delegate, a single delegate is below the threshold
+        assert_eq!(
+            carol.verify_quorum(&project, &[by_alice.clone(), by_carol])?,
+            None
+        );
+        // The same delegate signing twice counts once
+        assert_eq!(
+            carol.verify_quorum(&project, &[by_alice.clone(), by_alice.clone()])?,
+            None
+        );
+        assert_eq!(
+            carol.verify_quorum(&project, &[by_alice, by_bob])?,
+            Some(refs)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_after_rotation() -> Result<()> {
+        let root = Builder::new().prefix("test-rotation").tempdir()?;
+        let storage = Write::open(
+            root.path().join("git"),
+            UserInfo {
+                name: "Alice Liddell".to_owned(),
+                email: "alice@example.com".to_owned(),
+            },
+        )?;
+        let db = Arc::new(SledEventDatabase::new(root.path().join("db").as_path())?);
+
+        let mut alice = incepted(&storage, &db)?;
+        let bob = incepted(&storage, &db)?;
+        let project = alice.create_project(vec![alice.prefix().unwrap().clone()], 1)?;
+
+        let refs: BTreeMap<RefString, git2::Oid> = [(
+            RefString::try_from("refs/heads/main")?,
+            git2::Oid::from_str("4b825dc642cb6eb9a060e54bf8d69288fbee4904")?,
+        )]
+        .into_iter()
+        .collect();
+        let before = alice.sign_refs(refs.clone())?;
+        let rot = alice.identity.rotate()?;
+        alice.store.append(&rot)?;
+        let after = alice.sign_refs(refs)?;
+
+        // Signed with the keys established at the time
+        assert!(bob.validate(&before)?);
+        assert!(bob.validate(&after)?);
+        // but keys rotated away don't count towards a quorum
+        assert_eq!(bob.verify_quorum(&project, &[before])?, None);
+
+        Ok(())
     }
 }
@@ -1 +1,3 @@
 pub mod keri_controller;
+pub mod local_identity_server;
+pub mod signed_ref;
@@ -0,0 +1,461 @@
+//! Serve the person identity to the other devices of the same person
+//!
+//! This is the `local-identity-server` of the "Inception from multiple
+//! devices" flow: a new device fetches the person KEL, asks for its key to be
+//! added to the identity and pushes the events it signs afterwards.
+//!
+//! Requests are told apart by their first byte:
+//! * [`KEL_REQUEST`]: the server answers with the person KEL.
+//! * [`JOIN_REQUEST`], followed by the device key and a newline: the server
+//!   answers with a challenge line, which the device signs with its key and
+//!   sends back on a line of its own. If the signature is valid and the
+//!   approval hook accepts the device, the server rotates the person keys to
+//!   add the device key and answers with the updated KEL.
+//! * anything else is a stream of KERI messages handled by
+//!   [`keri::processor::async_processing::process`].
+//!
+//! Every event the server accepts is sent on the channel returned by
+//! [`LocalIdentityServer::new`], to be committed to the person KEL refs.
+//!
+//! The rotations adding a device key are generated by the server rather
+//! than by the joining device: only the keys committed to by the last
+//! establishment event can sign a rotation, and the server is the one
+//! holding them. The device proves it holds the key it asks to add, and
+//! signs the events it pushes afterwards with it.
+
+use std::sync::Arc;
+
+use async_std::channel::{self, Receiver, Sender};
+use async_std::io::{BufRead, BufReadExt, BufReader, ReadExt, WriteExt};
+use async_std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+
+use keri::derivation::{basic::Basic, self_signing::SelfSigning};
+use keri::event_message::event_msg_builder::EventMsgBuilder;
+use keri::event_message::signed_event_message::{Message, SignedEventMessage};
+use keri::event_message::EventTypeTag;
+use keri::event_parsing::{message::signed_event_stream, SignedEventData};
+use keri::keri::Keri;
+use keri::prefix::{
+    AttachedSignaturePrefix, BasicPrefix, IdentifierPrefix, Prefix, SelfSigningPrefix,
+};
+use keri::processor::{async_processing::process, EventProcessor};
+use keri::signer::KeyManager;
+
+/// First byte of a request for the person KEL
+pub const KEL_REQUEST: u8 = b'?';
+
+/// First byte of a request to add a device key to the person keys
+pub const JOIN_REQUEST: u8 = b'+';
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Server {
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+        #[error("Key manager lock poisoned")]
+        MutexPoisoned,
+        #[error("Server identity has no KEL")]
+        NotIncepted,
+        #[error("Malformed join request: {0}")]
+        Request(String),
+        #[error("Join challenge signature does not match the device key")]
+        Unauthenticated,
+        #[error("Join of device {0} was not approved")]
+        NotApproved(String),
+        #[error("Failed to process KERI messages: {0}")]
+        Process(String),
+        #[error("Events channel closed")]
+        Closed,
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Client {
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+        #[error("Malformed KEL in response: {0}")]
+        Parse(String),
+        #[error("Server rejected the join request")]
+        Rejected,
+    }
+}
+
+pub struct LocalIdentityServer<K>
+where
+    K: KeyManager + 'static,
+{
+    keri: Arc<Keri<K>>,
+    events: Sender<(IdentifierPrefix, Vec<u8>)>,
+    approve: Box<dyn Fn(&BasicPrefix) -> bool + Send + Sync>,
+}
+
+impl<K> LocalIdentityServer<K>
+where
+    K: KeyManager + Clone + Unpin + 'static,
+{
+    /// Serve the identity of `keri`
+    ///
+    /// `approve` is asked whether a device that proved it holds its key may
+    /// join the identity, e.g. by prompting the user.
+    ///
+    /// Returns the server together with the receiving end of the accepted
+    /// events, each one as its identifier and raw CESR message.
+    pub fn new(
+        keri: Arc<Keri<K>>,
+        approve: impl Fn(&BasicPrefix) -> bool + Send + Sync + 'static,
+    ) -> (Self, Receiver<(IdentifierPrefix, Vec<u8>)>) {
+        let (events, accepted) = channel::unbounded();
+        let server = Self {
+            keri,
+            events,
+            approve: Box::new(approve),
+        };
+        (server, accepted)
+    }
+
+    /// Accept connections on `addr` until the listener fails
+    pub async fn serve(&self, addr: impl ToSocketAddrs) -> Result<(), error::Server> {
+        self.listen(TcpListener::bind(addr).await?).await
+    }
+
+    /// Accept connections from `listener` until it fails
+    ///
+    /// Connections are handled one at a time, so concurrent joins can't
+    /// fork the person KEL. A failed connection is reported on stderr and
+    /// the server goes on with the next one.
+    pub async fn listen(&self, listener: TcpListener) -> Result<(), error::Server> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            if let Err(e) = self.handle(stream).await {
+                eprintln!("local-identity-server: {}: {}", peer, e);
+            }
+        }
+    }
+
+    /// Answer the request of a single connection
+    pub async fn handle(&self, stream: TcpStream) -> Result<(), error::Server> {
+        let mut first_byte = [0u8; 1];
+        if stream.peek(&mut first_byte).await? == 0 {
+            return Ok(());
+        }
+
+        let (mut reader, mut writer) = (&stream, &stream);
+        match first_byte[0] {
+            KEL_REQUEST => {
+                let kel = self.keri.get_kerl()?.ok_or(error::Server::NotIncepted)?;
+                writer.write_all(&kel).await?;
+            }
+            JOIN_REQUEST => {
+                let device = self
+                    .authenticate(&mut BufReader::new(reader), writer)
+                    .await?;
+                if !(self.approve)(&device) {
+                    return Err(error::Server::NotApproved(device.to_str()));
+                }
+                for rot in self.join(device)? {
+                    let raw = SignedEventData::from(&rot).to_cesr()?;
+                    self.events
+                        .send((self.keri.prefix().clone(), raw))
+                        .await
+                        .map_err(|_| error::Server::Closed)?;
+                }
+                let kel = self.keri.get_kerl()?.ok_or(error::Server::NotIncepted)?;
+                writer.write_all(&kel).await?;
+            }
+            first_byte => {
+                process(
+                    Arc::clone(&self.keri),
+                    &mut reader,
+                    &mut writer,
+                    first_byte,
+                    self.events.clone(),
+                )
+                .await
+                .map_err(error::Server::Process)?;
+            }
+        }
+        writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Read the device key of a join request and check that the device
+    /// holds it, by having it sign a random challenge
+    async fn authenticate<R>(
+        &self,
+        reader: &mut R,
+        mut writer: &TcpStream,
+    ) -> Result<BasicPrefix, error::Server>
+    where
+        R: BufRead + Unpin,
+    {
+        let mut request = String::new();
+        reader.read_line(&mut request).await?;
+        let device: BasicPrefix = request[1..]
+            .trim()
+            .parse()
+            .map_err(|_| error::Server::Request(request.clone()))?;
+
+        let challenge = rand::random::<[u8; 32]>()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        writer
+            .write_all(format!("{}\n", challenge).as_bytes())
+            .await?;
+        writer.flush().await?;
+
+        let mut response = String::new();
+        reader.read_line(&mut response).await?;
+        let signature: SelfSigningPrefix = response
+            .trim()
+            .parse()
+            .map_err(|_| error::Server::Request(response.clone()))?;
+        if !device.verify(challenge.as_bytes(), &signature)? {
+            return Err(error::Server::Unauthenticated);
+        }
+
+        Ok(device)
+    }
+
+    /// Rotate the person keys to add `device` to them
+    ///
+    /// A rotation must use exactly the keys committed to by the previous
+    /// establishment event, so the device key is first committed to as a
+    /// next key and only established by a second rotation. Device keys are
+    /// committed to again as they are, since only this server pre-rotates
+    /// its key, and any single key is enough to sign. The server key
+    /// manager only rotates once a rotation is processed, so a refused
+    /// rotation leaves it in step with the KEL.
+    fn join(&self, device: BasicPrefix) -> Result<Vec<SignedEventMessage>, error::Server> {
+        let prefix = self.keri.prefix();
+        let processor = EventProcessor::new(self.keri.db());
+        let key_manager = self.keri.key_manager();
+        let mut km = key_manager
+            .lock()
+            .map_err(|_| error::Server::MutexPoisoned)?;
+
+        let devices = processor
+            .compute_state(prefix)?
+            .ok_or(error::Server::NotIncepted)?
+            .current
+            .public_keys
+            .into_iter()
+            .skip(1)
+            .collect::<Vec<_>>();
+        let joined = devices
+            .iter()
+            .cloned()
+            .chain((!devices.contains(&device)).then_some(device))
+            .collect::<Vec<_>>();
+
+        let mut rotations = Vec::new();
+        for (keys, next_keys) in [(devices, joined.clone()), (joined.clone(), joined)] {
+            // The rotation is signed by a rotated copy of the key manager,
+            // which replaces it only once the rotation is accepted
+            let mut rotated = (*km).clone();
+            rotated.rotate()?;
+            let state = processor
+                .compute_state(prefix)?
+                .ok_or(error::Server::NotIncepted)?;
+            let rot = EventMsgBuilder::new(EventTypeTag::Rot)
+                .with_prefix(prefix)
+                .with_sn(state.sn + 1)
+                .with_previous_event(&state.last_event_digest)
+                .with_keys([vec![Basic::Ed25519.derive(km.next_public_key()?)], keys].concat())
+                .with_next_keys(
+                    [
+                        vec![Basic::Ed25519.derive(rotated.next_public_key()?)],
+                        next_keys,
+                    ]
+                    .concat(),
+                )
+                .build()?;
+            let rot = rot.sign(
+                vec![AttachedSignaturePrefix::new(
+                    SelfSigning::Ed25519Sha512,
+                    rotated.sign(&rot.serialize()?)?,
+                    0,
+                )],
+                None,
+            );
+            processor.process(Message::Event(Box::new(rot.clone())))?;
+            *km = rotated;
+            rotations.push(rot);
+        }
+
+        Ok(rotations)
+    }
+}
+
+/// Fetch the person KEL from the server at `addr`
+pub async fn fetch_kel(addr: impl ToSocketAddrs) -> Result<Vec<SignedEventMessage>, error::Client> {
+    parse_kel(&request(addr, &[KEL_REQUEST]).await?)
+}
+
+/// Ask the server at `addr` to add the key of `device` to the person keys
+///
+/// The key manager of the device signs the challenge of the server, to
+/// prove the device holds the key. Returns the person KEL, ending with the
+/// rotations adding the key.
+pub async fn join<K: KeyManager>(
+    addr: impl ToSocketAddrs,
+    device: &K,
+) -> Result<Vec<SignedEventMessage>, error::Client> {
+    let key = Basic::Ed25519.derive(device.public_key()?);
+    let stream = TcpStream::connect(addr).await?;
+    let (mut reader, mut writer) = (BufReader::new(&stream), &stream);
+    writer
+        .write_all(format!("{}{}\n", JOIN_REQUEST as char, key.to_str()).as_bytes())
+        .await?;
+
+    let mut challenge = String::new();
+    reader.read_line(&mut challenge).await?;
+    if challenge.is_empty() {
+        return Err(error::Client::Rejected);
+    }
+    let signature = SelfSigning::Ed25519Sha512.derive(device.sign(challenge.trim().as_bytes())?);
+    writer
+        .write_all(format!("{}\n", signature.to_str()).as_bytes())
+        .await?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut response = Vec::new();
+    reader.read_to_end(&mut response).await?;
+    // The server closes the connection without answering if the join
+    // was refused
+    if response.is_empty() {
+        return Err(error::Client::Rejected);
+    }
+    parse_kel(&response)
+}
+
+/// Push an event signed by this device to the server at `addr`
+///
+/// Returns the raw response of the server, the identifier state after the
+/// event was processed.
+pub async fn push(
+    addr: impl ToSocketAddrs,
+    event: &SignedEventMessage,
+) -> Result<Vec<u8>, error::Client> {
+    request(addr, &SignedEventData::from(event).to_cesr()?).await
+}
+
+async fn request(addr: impl ToSocketAddrs, bytes: &[u8]) -> Result<Vec<u8>, error::Client> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(bytes).await?;
+    // The server reads until the end of the request
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    Ok(response)
+}
+
+fn parse_kel(bytes: &[u8]) -> Result<Vec<SignedEventMessage>, error::Client> {
+    let (_, events) =
+        signed_event_stream(bytes).map_err(|e| error::Client::Parse(e.to_string()))?;
+    events
+        .into_iter()
+        .map(|event| match Message::try_from(event)? {
+            Message::Event(ev) => Ok(*ev),
+            _ => Err(error::Client::Parse("not a key event".into())),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_std::task;
+    use keri::database::sled::SledEventDatabase;
+    use keri::event::event_data::EventData;
+    use keri::signer::CryptoBox;
+    use tempfile::Builder;
+
+    use super::*;
+
+    type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+    #[test]
+    fn test_join_and_push() -> Result<()> {
+        let root = Builder::new().prefix("test-join").tempdir()?;
+        let db = Arc::new(SledEventDatabase::new(root.path())?);
+        let mut keri = Keri::new(db, Arc::new(Mutex::new(CryptoBox::new()?)))?;
+        keri.incept(None)?;
+        let keri = Arc::new(keri);
+        let (server, _accepted) = LocalIdentityServer::new(Arc::clone(&keri), |_| true);
+
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            task::spawn(async move { server.listen(listener).await });
+
+            assert_eq!(fetch_kel(addr).await?.len(), 1);
+
+            let device = CryptoBox::new()?;
+            let key = Basic::Ed25519.derive(device.public_key()?);
+            let kel = join(addr, &device).await?;
+            assert_eq!(kel.len(), 3);
+            let last = &kel[2].event_message;
+            let index = match last.event.get_event_data() {
+                EventData::Rot(rot) => rot.key_config.public_keys.iter().position(|k| *k == key),
+                _ => None,
+            }
+            .ok_or("device key is not established")?;
+
+            // The device signs an event on its own
+            let ixn = EventMsgBuilder::new(EventTypeTag::Ixn)
+                .with_prefix(keri.prefix())
+                .with_sn(last.event.get_sn() + 1)
+                .with_previous_event(&last.get_digest())
+                .build()?;
+            let ixn = ixn.sign(
+                vec![AttachedSignaturePrefix::new(
+                    SelfSigning::Ed25519Sha512,
+                    device.sign(&ixn.serialize()?)?,
+                    index as u16,
+                )],
+                None,
+            );
+            push(addr, &ixn).await?;
+
+            let state = keri.get_state()?.ok_or("person has no state")?;
+            assert_eq!(state.sn, 3);
+            assert!(state.current.public_keys.contains(&key));
+
+            Ok::<_, Box<dyn std::error::Error>>(())
+        })
+    }
+
+    #[test]
+    fn test_join_not_approved() -> Result<()> {
+        let root = Builder::new().prefix("test-join").tempdir()?;
+        let db = Arc::new(SledEventDatabase::new(root.path())?);
+        let mut keri = Keri::new(db, Arc::new(Mutex::new(CryptoBox::new()?)))?;
+        keri.incept(None)?;
+        let keri = Arc::new(keri);
+        let (server, _accepted) = LocalIdentityServer::new(Arc::clone(&keri), |_| false);
+
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            task::spawn(async move { server.listen(listener).await });
+
+            assert!(matches!(
+                join(addr, &CryptoBox::new()?).await,
+                Err(error::Client::Rejected)
+            ));
+            // The refused join doesn't stop the server
+            assert_eq!(fetch_kel(addr).await?.len(), 1);
+
+            Ok::<_, Box<dyn std::error::Error>>(())
+        })
+    }
+}
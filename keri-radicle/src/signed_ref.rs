@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use git_ref_format::RefString;
+
+use keri::event::sections::seal::EventSeal;
+use keri::event_parsing::{attachment::attachment, Attachment};
+use keri::prefix::{AttachedSignaturePrefix, IdentifierPrefix, Prefix};
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Parse {
+        #[error("Signed refs are not valid UTF-8")]
+        Utf8(#[from] std::str::Utf8Error),
+        #[error("Missing {0} section in signed refs")]
+        Missing(&'static str),
+        #[error("Malformed ref line: {0}")]
+        RefLine(String),
+        #[error(transparent)]
+        Oid(#[from] git2::Error),
+        #[error(transparent)]
+        RefFormat(#[from] git_ref_format::Error),
+        #[error(transparent)]
+        Keri(#[from] keri::error::Error),
+        #[error("Signature attachment is not a single seal signatures group")]
+        Attachment,
+    }
+}
+
+/// Refs of a peer signed by a KERI identity
+///
+/// The refs are signed with the keys established by the event `seal` points
+/// to, mirroring the `-F` (seal signatures group) attachment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedRef {
+    pub refs: BTreeMap<RefString, git2::Oid>,
+    pub signer: IdentifierPrefix,
+    pub seal: EventSeal,
+    pub signatures: Vec<AttachedSignaturePrefix>,
+}
+
+impl SignedRef {
+    /// Signed payload: one `<oid> <ref>` line per ref, sorted by ref name
+    pub fn canonical_refs(refs: &BTreeMap<RefString, git2::Oid>) -> Vec<u8> {
+        refs.iter()
+            .fold(String::new(), |acc, (name, oid)| {
+                [acc, format!("{} {}\n", oid, name)].concat()
+            })
+            .into_bytes()
+    }
+
+    /// Serialize to the format committed to git:
+    ///
+    /// ```text
+    /// <oid> <ref>
+    /// ...
+    ///
+    /// <signer>
+    /// <seal signatures group>
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let attachment =
+            Attachment::SealSignaturesGroups(vec![(self.seal.clone(), self.signatures.clone())]);
+        [
+            Self::canonical_refs(&self.refs),
+            format!("\n{}\n{}\n", self.signer.to_str(), attachment.to_cesr()).into_bytes(),
+        ]
+        .concat()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, error::Parse> {
+        let s = std::str::from_utf8(bytes)?;
+        let (refs_section, signature_section) = match s.rfind("\n\n") {
+            Some(i) => (&s[..i + 1], &s[i + 2..]),
+            // No refs at all
+            None => (
+                "",
+                s.strip_prefix('\n').ok_or(error::Parse::Missing("refs"))?,
+            ),
+        };
+
+        let mut refs = BTreeMap::new();
+        for line in refs_section.lines() {
+            let (oid, name) = line
+                .split_once(' ')
+                .ok_or_else(|| error::Parse::RefLine(line.to_string()))?;
+            refs.insert(RefString::try_from(name)?, git2::Oid::from_str(oid)?);
+        }
+
+        let mut lines = signature_section.lines();
+        let signer = lines
+            .next()
+            .ok_or(error::Parse::Missing("signer"))?
+            .parse()?;
+        let group = lines.next().ok_or(error::Parse::Missing("signature"))?;
+        let (seal, signatures) = match attachment(group.as_bytes()) {
+            Ok((rest, Attachment::SealSignaturesGroups(mut groups)))
+                if rest.is_empty() && groups.len() == 1 =>
+            {
+                groups.remove(0)
+            }
+            _ => return Err(error::Parse::Attachment),
+        };
+
+        Ok(Self {
+            refs,
+            signer,
+            seal,
+            signatures,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use keri::derivation::{
+        basic::Basic, self_addressing::SelfAddressing, self_signing::SelfSigning,
+    };
+    use keri::signer::{CryptoBox, KeyManager};
+
+    use super::*;
+
+    type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+    fn signed(refs: BTreeMap<RefString, git2::Oid>) -> Result<SignedRef> {
+        let km = CryptoBox::new()?;
+        let signer = IdentifierPrefix::Basic(Basic::Ed25519.derive(km.public_key()?));
+        let seal = EventSeal {
+            prefix: signer.clone(),
+            sn: 2,
+            event_digest: SelfAddressing::Blake3_256.derive(b"rotation"),
+        };
+        let signatures = vec![AttachedSignaturePrefix::new(
+            SelfSigning::Ed25519Sha512,
+            km.sign(&SignedRef::canonical_refs(&refs))?,
+            0,
+        )];
+        Ok(SignedRef {
+            refs,
+            signer,
+            seal,
+            signatures,
+        })
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() -> Result<()> {
+        let refs = [
+            (
+                "refs/heads/main",
+                "4b825dc642cb6eb9a060e54bf8d69288fbee4904",
+            ),
+            ("refs/tags/v1.0", "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"),
+        ]
+        .into_iter()
+        .map(|(name, oid)| -> Result<_> {
+            Ok((RefString::try_from(name)?, git2::Oid::from_str(oid)?))
+        })
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+        let signed_ref = signed(refs)?;
+        assert_eq!(SignedRef::from_bytes(&signed_ref.to_bytes())?, signed_ref);
+
+        let no_refs = signed(BTreeMap::new())?;
+        assert_eq!(SignedRef::from_bytes(&no_refs.to_bytes())?, no_refs);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed() -> Result<()> {
+        let signer = signed(BTreeMap::new())?.signer;
+        let signer_only = format!("\n{}\n", signer.to_str());
+
+        assert!(matches!(
+            SignedRef::from_bytes(signer_only.as_bytes()),
+            Err(error::Parse::Missing("signature"))
+        ));
+        assert!(matches!(
+            SignedRef::from_bytes(b"refs/heads/main\n\n"),
+            Err(error::Parse::RefLine(_))
+        ));
+
+        Ok(())
+    }
+}